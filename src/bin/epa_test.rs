@@ -189,12 +189,12 @@ fn main() {
         let p2 = c2b.as_ref().borrow().pos();
         // println!("{} {} {} / {} {} {}", p1.x, p1.y, p1.z, p2.x, p2.y, p2.z);
 
-        let gjkres = gjk(&c1c, &c2c, true);
+        let gjkres = gjk(c1c.as_ref(), c2c.as_ref(), true);
         let mut draw_points = false;
         if let Some(simplex) = gjkres {
             draw_points = true;
             
-            let (normal, depth, va, vb) = epa(&c1c, &c2c, simplex);
+            let (normal, depth, va, vb) = epa(c1c.as_ref(), c2c.as_ref(), simplex);
 
             println!("{:?} {:?}", va, vb);
             let pa = c1b.as_ref().borrow().to_global(va);
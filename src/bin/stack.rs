@@ -5,7 +5,7 @@ use stuff_lib::body::Body;
 use stuff_lib::config::*;
 use stuff_lib::particle::*;
 use stuff_lib::particle_constraint::*;
-use stuff_lib::collision::chull::cube_polyhedra;
+use stuff_lib::collision::chull::{box_polyhedra, cube_polyhedra};
 use stuff_lib::collision::collider::*;
 use stuff_lib::constraint::Constraint;
 use stuff_lib::body::*;
@@ -78,20 +78,25 @@ fn main() {
 
     let mut physics = Physics::new(vec3(0.0, -5.0, 0.0), SUBS, ITER);
 
+    // Thin static slab, so that continuous collision keeps the cubes from
+    // tunnelling through it
     let base_body = physics.add_body(
         RigidBody::new(
-            vec3(0.0, -5.0, 0.0),
+            vec3(0.0, -0.55, 0.0),
             Quat::one(),
-            0.0/100.0,
+            0.0,
             zeroinertia_mass()
         )
     );
     physics.add_collider(
         PolyhedraCollider::new(
             &(base_body.clone() as Rc<RefCell<dyn Body>>),
-            cube_polyhedra(9.0)
+            box_polyhedra(vec3(4.5, 0.05, 4.5))
         )
     );
+    base_cube.set_transformation(
+        Mat4::from_translation(vec3(0.0, -0.55, 0.0))*Mat4::from_nonuniform_scale(4.5, 0.05, 4.5)
+    );
 
     let mut bodies = Vec::new();
     for i in 0..CUBES {
@@ -109,6 +114,7 @@ fn main() {
                 cube_polyhedra(1.0)
             )
         );
+        bodies[bodies.len() - 1].as_ref().borrow_mut().set_ccd(true);
     }
     let bodies = bodies;
 
@@ -122,10 +128,6 @@ fn main() {
 
         physics.update(dt*1.0);
 
-        base_cube.set_transformation(
-            base_body.as_ref().borrow().get_matrix()*Mat4::from_scale(4.5)
-        );
-
         for (i, cube) in cubes.iter_mut().enumerate() {
             cube.set_transformation(
                 bodies[i].as_ref().borrow().get_matrix()*Mat4::from_scale(0.5)
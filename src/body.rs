@@ -35,10 +35,24 @@ macro_rules! rigidbody_common {
             self.invinertia
         }
 
+        fn inertia_world(&self) -> Mat3 {
+            let r = Mat3::from(self.apos*self.inertia_frame);
+            r*self.inertia*r.transpose()
+        }
+
+        fn invinertia_world(&self) -> Mat3 {
+            let r = Mat3::from(self.apos*self.inertia_frame);
+            r*self.invinertia*r.transpose()
+        }
+
         fn apos(&self) -> Quat {
             self.apos
         }
 
+        fn ccd(&self) -> bool {
+            self.ccd
+        }
+
         fn apos_prev(&self) -> Quat {
             self.apos_prev
         }
@@ -61,7 +75,7 @@ macro_rules! rigidbody_common {
             self.pos += self.vel*dt;
 
             self.apos_prev = self.apos;
-            self.avel += self.aacc*dt - dt*self.invinertia*self.avel.cross(self.inertia*self.avel);
+            self.avel += self.aacc*dt - dt*self.invinertia_world()*self.avel.cross(self.inertia_world()*self.avel);
             self.apos = self.apos + (dt*0.5*Quat::new(0.0, self.avel.x, self.avel.y, self.avel.z))*self.apos;
             self.apos = self.apos.normalize();
 
@@ -97,11 +111,11 @@ macro_rules! rigidbody_common {
         }
 
         fn add_force_at(&mut self, f: Vec3, at: Vec3) {
-            self.aacc += self.invinertia*at.cross(f);
+            self.aacc += self.invinertia_world()*at.cross(f);
         }
 
         fn add_torque(&mut self, t: Vec3) {
-            self.aacc += self.invinertia*t;
+            self.aacc += self.invinertia_world()*t;
         }
 
         fn update_velocity(&mut self, dt: f32) {
@@ -130,12 +144,26 @@ pub trait Body {
     }
     fn iterate(&mut self);
 
+    // Continuous collision detection, for bodies fast enough to pass through
+    // thin geometry within one substep
+    fn ccd(&self) -> bool {
+        false
+    }
+
+    // Principal moments, diagonal in the body's principal frame
     fn inertia(&self) -> Mat3 {
         Mat3::zero()
     }
     fn invinertia(&self) -> Mat3 {
         Mat3::zero()
     }
+    // Rotated along with the body, for use in world-space formulas
+    fn inertia_world(&self) -> Mat3 {
+        Mat3::zero()
+    }
+    fn invinertia_world(&self) -> Mat3 {
+        Mat3::zero()
+    }
 
     fn apos(&self) -> Quat {
         Quat::one()
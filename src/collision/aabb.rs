@@ -0,0 +1,137 @@
+use super::collider::*;
+use three_d::*;
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Aabb {
+    pub min: Vec3,
+    pub max: Vec3,
+}
+
+impl Aabb {
+    pub fn new(min: Vec3, max: Vec3) -> Self {
+        Self { min, max }
+    }
+
+    // Exact world-space bounds of any convex collider, taken from its support
+    // points along the six principal directions.
+    pub fn from_collider<T: Collider + ?Sized>(collider: &T) -> Self {
+        let max = vec3(
+            collider.support(Vec3::unit_x()).x,
+            collider.support(Vec3::unit_y()).y,
+            collider.support(Vec3::unit_z()).z,
+        );
+        let min = vec3(
+            collider.support(-Vec3::unit_x()).x,
+            collider.support(-Vec3::unit_y()).y,
+            collider.support(-Vec3::unit_z()).z,
+        );
+
+        Self { min, max }
+    }
+
+    pub fn overlaps(&self, other: &Aabb) -> bool {
+        self.min.x <= other.max.x
+            && self.max.x >= other.min.x
+            && self.min.y <= other.max.y
+            && self.max.y >= other.min.y
+            && self.min.z <= other.max.z
+            && self.max.z >= other.min.z
+    }
+
+    pub fn union(&self, other: &Aabb) -> Self {
+        Self {
+            min: vec3(
+                self.min.x.min(other.min.x),
+                self.min.y.min(other.min.y),
+                self.min.z.min(other.min.z),
+            ),
+            max: vec3(
+                self.max.x.max(other.max.x),
+                self.max.y.max(other.max.y),
+                self.max.z.max(other.max.z),
+            ),
+        }
+    }
+
+    pub fn expand(&self, margin: f32) -> Self {
+        let margin = vec3(margin, margin, margin);
+        Self {
+            min: self.min - margin,
+            max: self.max + margin,
+        }
+    }
+
+    // Box covering both the current bounds and the bounds moved by `dx`.
+    pub fn swept(&self, dx: Vec3) -> Self {
+        self.union(&Self {
+            min: self.min + dx,
+            max: self.max + dx,
+        })
+    }
+
+    pub fn from_points(points: &[Vec3]) -> Self {
+        let mut min = vec3(f32::INFINITY, f32::INFINITY, f32::INFINITY);
+        let mut max = vec3(f32::NEG_INFINITY, f32::NEG_INFINITY, f32::NEG_INFINITY);
+        for p in points {
+            min = vec3(min.x.min(p.x), min.y.min(p.y), min.z.min(p.z));
+            max = vec3(max.x.max(p.x), max.y.max(p.y), max.z.max(p.z));
+        }
+
+        Self { min, max }
+    }
+
+    pub fn center(&self) -> Vec3 {
+        (self.min + self.max) * 0.5
+    }
+
+    pub fn contains(&self, other: &Aabb) -> bool {
+        self.min.x <= other.min.x
+            && self.min.y <= other.min.y
+            && self.min.z <= other.min.z
+            && self.max.x >= other.max.x
+            && self.max.y >= other.max.y
+            && self.max.z >= other.max.z
+    }
+
+    pub fn surface_area(&self) -> f32 {
+        let d = self.max - self.min;
+        2.0 * (d.x * d.y + d.y * d.z + d.z * d.x)
+    }
+
+    pub fn distance2(&self, point: Vec3) -> f32 {
+        let clamped = vec3(
+            point.x.clamp(self.min.x, self.max.x),
+            point.y.clamp(self.min.y, self.max.y),
+            point.z.clamp(self.min.z, self.max.z),
+        );
+        clamped.distance2(point)
+    }
+
+    // Slab test. Returns the ray parameter at which the ray enters the box,
+    // 0 if it starts inside.
+    pub fn ray_hit(&self, origin: Vec3, dir: Vec3, max_t: f32) -> Option<f32> {
+        let mut t_min: f32 = 0.0;
+        let mut t_max = max_t;
+        for axis in 0..3 {
+            let (o, d, lo, hi) = (origin[axis], dir[axis], self.min[axis], self.max[axis]);
+            if d.abs() < f32::EPSILON {
+                if o < lo || o > hi {
+                    return None;
+                }
+                continue;
+            }
+            let mut t0 = (lo - o) / d;
+            let mut t1 = (hi - o) / d;
+            if t0 > t1 {
+                std::mem::swap(&mut t0, &mut t1);
+            }
+            t_min = t_min.max(t0);
+            t_max = t_max.min(t1);
+            if t_min > t_max {
+                return None;
+            }
+        }
+
+        Some(t_min)
+    }
+}
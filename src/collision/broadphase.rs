@@ -0,0 +1,257 @@
+use super::aabb::*;
+use std::vec::Vec;
+use three_d::*;
+
+// Collider ids are the indices the colliders have in `Physics`. Boxes are
+// passed in tight, and any margin around them is up to the implementation.
+pub trait Broadphase {
+    fn insert(&mut self, id: usize, aabb: Aabb);
+    fn remove(&mut self, id: usize);
+    fn update(&mut self, id: usize, aabb: Aabb);
+
+    // Every pair (i, j), i < j, whose bounding boxes overlap.
+    fn pairs(&mut self) -> Vec<(usize, usize)>;
+
+    fn query_aabb(&self, aabb: &Aabb) -> Vec<usize>;
+    fn query_ray(&self, origin: Vec3, dir: Vec3, max_t: f32) -> Vec<usize>;
+}
+
+fn axis_value(v: Vec3, axis: usize) -> f32 {
+    match axis {
+        0 => v.x,
+        1 => v.y,
+        _ => v.z,
+    }
+}
+
+// Boxes are grown by `margin` as they come in.
+pub struct SweepAndPrune {
+    aabbs: Vec<Option<Aabb>>,
+    // ids sorted by the lower bound on `axis`, kept between frames so that
+    // the insertion sort only has to fix up the few ids that moved past each other
+    order: Vec<usize>,
+    axis: usize,
+    margin: f32,
+}
+
+impl SweepAndPrune {
+    pub fn new(margin: f32) -> Self {
+        Self {
+            aabbs: Vec::new(),
+            order: Vec::new(),
+            axis: 0,
+            margin,
+        }
+    }
+
+    // Sweep along the axis the boxes are spread the most on. Only picked again
+    // when colliders come or go, since switching axis throws away the order.
+    fn choose_axis(&mut self) {
+        let mut sum = Vec3::zero();
+        let mut sum2 = Vec3::zero();
+        for aabb in self.aabbs.iter().flatten() {
+            let c = aabb.center();
+            sum += c;
+            sum2 += vec3(c.x * c.x, c.y * c.y, c.z * c.z);
+        }
+        let n = self.order.len() as f32;
+        if n < 2.0 {
+            return;
+        }
+        let variance = sum2 / n - vec3(sum.x * sum.x, sum.y * sum.y, sum.z * sum.z) / (n * n);
+
+        self.axis = if variance.x >= variance.y && variance.x >= variance.z {
+            0
+        } else if variance.y >= variance.z {
+            1
+        } else {
+            2
+        };
+    }
+
+    fn min_of(&self, id: usize) -> f32 {
+        axis_value(self.aabbs[id].unwrap().min, self.axis)
+    }
+
+    fn sort(&mut self) {
+        for i in 1..self.order.len() {
+            let id = self.order[i];
+            let key = self.min_of(id);
+
+            let mut j = i;
+            while j > 0 && self.min_of(self.order[j - 1]) > key {
+                self.order[j] = self.order[j - 1];
+                j -= 1;
+            }
+            self.order[j] = id;
+        }
+    }
+}
+
+impl Broadphase for SweepAndPrune {
+    fn insert(&mut self, id: usize, aabb: Aabb) {
+        if self.aabbs.len() <= id {
+            self.aabbs.resize(id + 1, None);
+        }
+        if self.aabbs[id].is_none() {
+            self.order.push(id);
+        }
+        self.aabbs[id] = Some(aabb.expand(self.margin));
+        self.choose_axis();
+    }
+
+    fn remove(&mut self, id: usize) {
+        if id < self.aabbs.len() && self.aabbs[id].take().is_some() {
+            self.order.retain(|i| *i != id);
+            self.choose_axis();
+        }
+    }
+
+    fn update(&mut self, id: usize, aabb: Aabb) {
+        match self.aabbs.get_mut(id) {
+            Some(Some(old)) => *old = aabb.expand(self.margin),
+            _ => self.insert(id, aabb),
+        }
+    }
+
+    fn pairs(&mut self) -> Vec<(usize, usize)> {
+        self.sort();
+
+        let mut result = Vec::new();
+        let mut active: Vec<usize> = Vec::new();
+        for &id in &self.order {
+            let aabb = self.aabbs[id].unwrap();
+            let min = axis_value(aabb.min, self.axis);
+
+            active.retain(|other| axis_value(self.aabbs[*other].unwrap().max, self.axis) >= min);
+            for &other in &active {
+                if aabb.overlaps(&self.aabbs[other].unwrap()) {
+                    result.push((other.min(id), other.max(id)));
+                }
+            }
+            active.push(id);
+        }
+
+        result
+    }
+
+    fn query_aabb(&self, aabb: &Aabb) -> Vec<usize> {
+        self.order
+            .iter()
+            .copied()
+            .filter(|id| self.aabbs[*id].unwrap().overlaps(aabb))
+            .collect()
+    }
+
+    fn query_ray(&self, origin: Vec3, dir: Vec3, max_t: f32) -> Vec<usize> {
+        self.order
+            .iter()
+            .copied()
+            .filter(|id| {
+                self.aabbs[*id]
+                    .unwrap()
+                    .ray_hit(origin, dir, max_t)
+                    .is_some()
+            })
+            .collect()
+    }
+}
+
+#[cfg(test)]
+mod broadphase_test {
+    use super::*;
+
+    fn unit_box(x: f32, y: f32, z: f32) -> Aabb {
+        Aabb::new(
+            vec3(x - 0.5, y - 0.5, z - 0.5),
+            vec3(x + 0.5, y + 0.5, z + 0.5),
+        )
+    }
+
+    fn brute_force(aabbs: &[Aabb]) -> Vec<(usize, usize)> {
+        let mut result = Vec::new();
+        for i in 0..aabbs.len() {
+            for j in (i + 1)..aabbs.len() {
+                if aabbs[i].overlaps(&aabbs[j]) {
+                    result.push((i, j));
+                }
+            }
+        }
+        result
+    }
+
+    #[test]
+    fn sap_matches_brute_force() {
+        let mut aabbs: Vec<Aabb> = (0..40)
+            .map(|i| {
+                let f = i as f32;
+                unit_box((f * 0.73).sin() * 4.0, (f * 1.31).cos() * 4.0, f * 0.1)
+            })
+            .collect();
+
+        let mut sap = SweepAndPrune::new(0.0);
+        for (i, aabb) in aabbs.iter().enumerate() {
+            sap.insert(i, *aabb);
+        }
+
+        for frame in 0..5 {
+            for (i, aabb) in aabbs.iter_mut().enumerate() {
+                let shift = vec3((i + frame) as f32 * 0.37 % 1.0 - 0.5, 0.2, 0.0);
+                *aabb = Aabb::new(aabb.min + shift, aabb.max + shift);
+                sap.update(i, *aabb);
+            }
+
+            let mut pairs = sap.pairs();
+            pairs.sort();
+            assert_eq!(pairs, brute_force(&aabbs));
+        }
+    }
+
+    #[test]
+    fn sap_keeps_axis() {
+        let mut sap = SweepAndPrune::new(0.0);
+        for i in 0..10 {
+            sap.insert(i, unit_box(i as f32 * 2.0, 0.0, 0.0));
+        }
+        assert_eq!(sap.axis, 0);
+
+        // Spread out along y now, but the order along x is still good to reuse
+        for i in 0..10 {
+            sap.update(i, unit_box(i as f32 * 0.1, i as f32 * 3.0, 0.0));
+        }
+        sap.pairs();
+        assert_eq!(sap.axis, 0);
+        assert_eq!(sap.order, (0..10).collect::<Vec<usize>>());
+
+        sap.insert(10, unit_box(0.0, 30.0, 0.0));
+        assert_eq!(sap.axis, 1);
+    }
+
+    #[test]
+    fn sap_margin() {
+        // 0.15 apart, so only the two margins together close the gap
+        let mut sap = SweepAndPrune::new(0.1);
+        sap.insert(0, unit_box(0.0, 0.0, 0.0));
+        sap.insert(1, unit_box(1.15, 0.0, 0.0));
+        assert_eq!(sap.pairs(), vec![(0, 1)]);
+
+        let mut sap = SweepAndPrune::new(0.05);
+        sap.insert(0, unit_box(0.0, 0.0, 0.0));
+        sap.insert(1, unit_box(1.15, 0.0, 0.0));
+        assert!(sap.pairs().is_empty());
+    }
+
+    #[test]
+    fn sap_remove() {
+        let mut sap = SweepAndPrune::new(0.0);
+        sap.insert(0, unit_box(0.0, 0.0, 0.0));
+        sap.insert(1, unit_box(0.5, 0.0, 0.0));
+        sap.insert(2, unit_box(0.9, 0.0, 0.0));
+
+        sap.remove(1);
+
+        let mut pairs = sap.pairs();
+        pairs.sort();
+        assert_eq!(pairs, vec![(0, 2)]);
+    }
+}
@@ -0,0 +1,307 @@
+use super::aabb::*;
+use super::broadphase::*;
+use std::vec::Vec;
+use three_d::*;
+
+const NULL: usize = usize::MAX;
+
+#[derive(Clone)]
+struct Node {
+    aabb: Aabb,
+    parent: usize,
+    children: [usize; 2],
+    height: usize,
+    id: usize,
+}
+
+impl Node {
+    fn is_leaf(&self) -> bool {
+        self.children[0] == NULL
+    }
+}
+
+// Dynamic bounding volume tree. Leaves store fat boxes (tight box grown by
+// `margin`) so that a leaf only has to be reinserted once its collider moves
+// out of it.
+pub struct DynamicTree {
+    nodes: Vec<Node>,
+    free: Vec<usize>,
+    root: usize,
+    leaves: Vec<usize>,
+    margin: f32,
+}
+
+impl DynamicTree {
+    pub fn new(margin: f32) -> Self {
+        Self {
+            nodes: Vec::new(),
+            free: Vec::new(),
+            root: NULL,
+            leaves: Vec::new(),
+            margin,
+        }
+    }
+
+    fn allocate(&mut self, aabb: Aabb, id: usize) -> usize {
+        let node = Node {
+            aabb,
+            parent: NULL,
+            children: [NULL, NULL],
+            height: 0,
+            id,
+        };
+
+        if let Some(index) = self.free.pop() {
+            self.nodes[index] = node;
+            index
+        } else {
+            self.nodes.push(node);
+            self.nodes.len() - 1
+        }
+    }
+
+    fn leaf(&self, id: usize) -> Option<usize> {
+        self.leaves.get(id).copied().filter(|leaf| *leaf != NULL)
+    }
+
+    pub fn height(&self) -> usize {
+        if self.root == NULL {
+            0
+        } else {
+            self.nodes[self.root].height
+        }
+    }
+
+    fn refit(&mut self, mut index: usize) {
+        while index != NULL {
+            let [c1, c2] = self.nodes[index].children;
+            self.nodes[index].aabb = self.nodes[c1].aabb.union(&self.nodes[c2].aabb);
+            self.nodes[index].height = 1 + self.nodes[c1].height.max(self.nodes[c2].height);
+            index = self.nodes[index].parent;
+        }
+    }
+
+    fn insert_leaf(&mut self, leaf: usize) {
+        if self.root == NULL {
+            self.root = leaf;
+            self.nodes[leaf].parent = NULL;
+            return;
+        }
+
+        // Descend towards the sibling that grows the total surface area the least
+        let leaf_aabb = self.nodes[leaf].aabb;
+        let mut index = self.root;
+        while !self.nodes[index].is_leaf() {
+            let area = self.nodes[index].aabb.surface_area();
+            let combined = self.nodes[index].aabb.union(&leaf_aabb).surface_area();
+
+            let cost = 2.0 * combined;
+            let inheritance = 2.0 * (combined - area);
+
+            let child_cost = |child: usize| -> f32 {
+                let node = &self.nodes[child];
+                let union = node.aabb.union(&leaf_aabb).surface_area();
+                if node.is_leaf() {
+                    union + inheritance
+                } else {
+                    union - node.aabb.surface_area() + inheritance
+                }
+            };
+            let [c1, c2] = self.nodes[index].children;
+            let cost1 = child_cost(c1);
+            let cost2 = child_cost(c2);
+
+            if cost < cost1 && cost < cost2 {
+                break;
+            }
+            index = if cost1 < cost2 { c1 } else { c2 };
+        }
+
+        let sibling = index;
+        let old_parent = self.nodes[sibling].parent;
+        let new_parent = self.allocate(leaf_aabb.union(&self.nodes[sibling].aabb), NULL);
+        self.nodes[new_parent].parent = old_parent;
+        self.nodes[new_parent].children = [sibling, leaf];
+        self.nodes[sibling].parent = new_parent;
+        self.nodes[leaf].parent = new_parent;
+
+        if old_parent == NULL {
+            self.root = new_parent;
+        } else {
+            let children = &mut self.nodes[old_parent].children;
+            if children[0] == sibling {
+                children[0] = new_parent;
+            } else {
+                children[1] = new_parent;
+            }
+        }
+
+        self.refit(new_parent);
+    }
+
+    fn remove_leaf(&mut self, leaf: usize) {
+        if leaf == self.root {
+            self.root = NULL;
+            return;
+        }
+
+        let parent = self.nodes[leaf].parent;
+        let grand_parent = self.nodes[parent].parent;
+        let [c1, c2] = self.nodes[parent].children;
+        let sibling = if c1 == leaf { c2 } else { c1 };
+
+        if grand_parent == NULL {
+            self.root = sibling;
+            self.nodes[sibling].parent = NULL;
+        } else {
+            let children = &mut self.nodes[grand_parent].children;
+            if children[0] == parent {
+                children[0] = sibling;
+            } else {
+                children[1] = sibling;
+            }
+            self.nodes[sibling].parent = grand_parent;
+            self.refit(grand_parent);
+        }
+
+        self.free.push(parent);
+    }
+
+    fn query<F: Fn(&Aabb) -> bool>(&self, test: F) -> Vec<usize> {
+        let mut result = Vec::new();
+        if self.root == NULL {
+            return result;
+        }
+
+        let mut stack = vec![self.root];
+        while let Some(index) = stack.pop() {
+            let node = &self.nodes[index];
+            if !test(&node.aabb) {
+                continue;
+            }
+            if node.is_leaf() {
+                result.push(node.id);
+            } else {
+                stack.extend(node.children);
+            }
+        }
+
+        result
+    }
+
+    pub fn query_aabb(&self, aabb: &Aabb) -> Vec<usize> {
+        self.query(|node| node.overlaps(aabb))
+    }
+
+    pub fn query_sphere(&self, center: Vec3, radius: f32) -> Vec<usize> {
+        self.query(|node| node.distance2(center) <= radius * radius)
+    }
+
+    pub fn query_ray(&self, origin: Vec3, dir: Vec3, max_t: f32) -> Vec<usize> {
+        self.query(|node| node.ray_hit(origin, dir, max_t).is_some())
+    }
+}
+
+impl Broadphase for DynamicTree {
+    fn insert(&mut self, id: usize, aabb: Aabb) {
+        if self.leaf(id).is_some() {
+            self.remove(id);
+        }
+
+        let leaf = self.allocate(aabb.expand(self.margin), id);
+        if self.leaves.len() <= id {
+            self.leaves.resize(id + 1, NULL);
+        }
+        self.leaves[id] = leaf;
+        self.insert_leaf(leaf);
+    }
+
+    fn remove(&mut self, id: usize) {
+        if let Some(leaf) = self.leaf(id) {
+            self.remove_leaf(leaf);
+            self.free.push(leaf);
+            self.leaves[id] = NULL;
+        }
+    }
+
+    fn update(&mut self, id: usize, aabb: Aabb) {
+        match self.leaf(id) {
+            Some(leaf) if self.nodes[leaf].aabb.contains(&aabb) => {}
+            Some(leaf) => {
+                self.remove_leaf(leaf);
+                self.nodes[leaf].aabb = aabb.expand(self.margin);
+                self.insert_leaf(leaf);
+            }
+            None => self.insert(id, aabb),
+        }
+    }
+
+    fn pairs(&mut self) -> Vec<(usize, usize)> {
+        let mut result = Vec::new();
+        for (id, leaf) in self.leaves.iter().enumerate() {
+            if *leaf == NULL {
+                continue;
+            }
+            for other in self.query_aabb(&self.nodes[*leaf].aabb) {
+                if id < other {
+                    result.push((id, other));
+                }
+            }
+        }
+
+        result
+    }
+
+    fn query_aabb(&self, aabb: &Aabb) -> Vec<usize> {
+        DynamicTree::query_aabb(self, aabb)
+    }
+
+    fn query_ray(&self, origin: Vec3, dir: Vec3, max_t: f32) -> Vec<usize> {
+        DynamicTree::query_ray(self, origin, dir, max_t)
+    }
+}
+
+#[cfg(test)]
+mod bvh_test {
+    use super::*;
+
+    fn unit_box(x: f32, y: f32, z: f32) -> Aabb {
+        Aabb::new(
+            vec3(x - 0.5, y - 0.5, z - 0.5),
+            vec3(x + 0.5, y + 0.5, z + 0.5),
+        )
+    }
+
+    #[test]
+    fn tree_queries() {
+        let mut tree = DynamicTree::new(0.0);
+        for i in 0..32 {
+            tree.insert(i, unit_box(i as f32 * 2.0, 0.0, 0.0));
+        }
+        assert!(tree.height() < 32);
+
+        let mut hits = tree.query_aabb(&unit_box(4.0, 0.0, 0.0).expand(1.6));
+        hits.sort();
+        assert_eq!(hits, vec![1, 2, 3]);
+
+        let mut hits = tree.query_sphere(vec3(11.0, 0.0, 0.0), 0.6);
+        hits.sort();
+        assert_eq!(hits, vec![5, 6]);
+
+        let mut hits = tree.query_ray(vec3(-5.0, 0.0, 0.0), Vec3::unit_x(), 7.0);
+        hits.sort();
+        assert_eq!(hits, vec![0, 1]);
+
+        tree.remove(1);
+        tree.update(2, unit_box(100.0, 0.0, 0.0));
+        let mut hits = tree.query_aabb(&unit_box(4.0, 0.0, 0.0).expand(1.6));
+        hits.sort();
+        assert_eq!(hits, vec![3]);
+
+        let mut pairs = tree.pairs();
+        pairs.sort();
+        assert!(pairs.is_empty());
+        tree.update(4, unit_box(6.5, 0.0, 0.0));
+        assert_eq!(tree.pairs(), vec![(3, 4)]);
+    }
+}
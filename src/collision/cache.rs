@@ -0,0 +1,139 @@
+use super::contact::*;
+use std::collections::HashMap;
+use three_d::*;
+
+// What is kept of a contact from one step to the next
+#[derive(Debug, Clone, Copy)]
+pub struct CachedContact {
+    // Body-local points static friction pins together
+    pub anchors: (Vec3, Vec3),
+    // Accumulated normal lambda of the last step
+    pub lambda: f32,
+}
+
+// Contacts of the last step, by collider pair (i, j), i < j, and feature
+#[derive(Default)]
+pub struct ContactCache {
+    pairs: HashMap<(usize, usize), HashMap<ContactFeature, CachedContact>>,
+}
+
+impl ContactCache {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn get(&self, pair: (usize, usize), feature: ContactFeature) -> Option<&CachedContact> {
+        self.pairs.get(&pair)?.get(&feature)
+    }
+
+    #[cfg(test)]
+    pub fn contacts(
+        &self,
+        pair: (usize, usize),
+    ) -> impl Iterator<Item = (&ContactFeature, &CachedContact)> {
+        self.pairs.get(&pair).into_iter().flatten()
+    }
+
+    pub fn insert(
+        &mut self,
+        pair: (usize, usize),
+        feature: ContactFeature,
+        contact: CachedContact,
+    ) {
+        self.pairs.entry(pair).or_default().insert(feature, contact);
+    }
+
+    pub fn clear(&mut self) {
+        self.pairs.clear();
+    }
+
+    #[cfg(test)]
+    pub fn len(&self) -> usize {
+        self.pairs.values().map(|contacts| contacts.len()).sum()
+    }
+
+    #[cfg(test)]
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+}
+
+#[cfg(test)]
+mod cache_test {
+    use super::*;
+    use crate::body::*;
+    use crate::config::*;
+    use crate::constraint::*;
+    use crate::cube::*;
+    use crate::inertiatensor::*;
+    use crate::material::Material;
+    use crate::rigidbody_constraint::*;
+    use std::cell::RefCell;
+    use std::rc::Rc;
+
+    // Cube of side 1 sunk 0.01 into static ground at y = 0.5, touching at the
+    // given x offsets
+    fn contact(
+        ground: &Rc<RefCell<dyn Body>>,
+        cube: &Rc<RefCell<dyn Body>>,
+        xs: &[f32],
+        features: Vec<ContactFeature>,
+    ) -> RColl {
+        let y = cube.as_ref().borrow().pos().y - 0.5;
+        RColl::new(
+            [ground.clone(), cube.clone()],
+            xs.iter()
+                .map(|x| (vec3(*x, 0.5, 0.0), vec3(*x, y, 0.0)))
+                .collect(),
+            vec3(0.0, 1.0, 0.0),
+            Material::default(),
+        )
+        .with_features(features)
+    }
+
+    #[test]
+    fn warm_start() {
+        let ground: Rc<RefCell<dyn Body>> = Rc::new(RefCell::new(RigidBody::new(
+            Vec3::zero(),
+            Quat::one(),
+            0.0,
+            zeroinertia_mass(),
+        )));
+        let cube: Rc<RefCell<dyn Body>> = Rc::new(RefCell::new(RigidBody::new(
+            vec3(0.0, 0.99, 0.0),
+            Quat::one(),
+            1.0,
+            cubeinertia_mass(1.0),
+        )));
+
+        let mut cache = ContactCache::new();
+        let mut first = contact(&ground, &cube, &[-0.5, 0.5], vec![3, 5]);
+        first.iterate(1.0 / 60.0);
+        assert!(cache.is_empty());
+        first.store(&mut cache, (0, 1));
+        assert_eq!(cache.len(), 2);
+        assert!(cache.contacts((0, 1)).all(|(_, c)| c.lambda > 0.0));
+        assert!(cache.contacts((1, 2)).next().is_none());
+
+        // Back into the ground, slid along x, with one corner replaced
+        let y = cube.as_ref().borrow().pos().y;
+        cube.as_ref()
+            .borrow_mut()
+            .update_pos(vec3(0.1, 0.99 - y, 0.0));
+        let mut second = contact(&ground, &cube, &[-0.4, 0.4], vec![3, 8]);
+        let corner = cube.as_ref().borrow().to_local(vec3(0.4, 0.49, 0.0));
+        second.warm_start(&cache, (0, 1));
+        // Nothing is moved, only the matched contact starts from its lambda
+        assert!((cube.as_ref().borrow().pos().y - 0.99).abs() < 1e-6);
+
+        let mut next = ContactCache::new();
+        second.store(&mut next, (0, 1));
+        let (kept, old) = (next.get((0, 1), 3).unwrap(), cache.get((0, 1), 3).unwrap());
+        assert_eq!(kept.anchors, old.anchors);
+        assert_eq!(kept.lambda, old.lambda * WARM_START);
+        let fresh = next.get((0, 1), 8).unwrap();
+        assert!((fresh.anchors.1 - corner).magnitude() < 1e-6);
+        assert_eq!(fresh.lambda, 0.0);
+        assert!(next.get((0, 1), 5).is_none());
+    }
+}
@@ -1,3 +1,5 @@
+use std::collections::HashMap;
+use std::f32::consts::PI;
 use std::vec::Vec;
 use three_d::*;
 
@@ -28,3 +30,636 @@ pub fn cube_polyhedra(sidelen: f32) -> (Vec<Vec3>, Vec<Vec<usize>>) {
         ),
     )
 }
+
+// Box with independent half extents, centered on the origin
+pub fn box_polyhedra(half_extents: Vec3) -> (Vec<Vec3>, Vec<Vec<usize>>) {
+    let (vertices, faces) = cube_polyhedra(2.0);
+    (
+        vertices
+            .into_iter()
+            .map(|v| {
+                vec3(
+                    v.x * half_extents.x,
+                    v.y * half_extents.y,
+                    v.z * half_extents.z,
+                )
+            })
+            .collect(),
+        faces,
+    )
+}
+
+// Regular polygon of `sides` corners on a circle of `radius` in the xz plane
+// at height y, going from +x towards +z
+fn ring(sides: usize, radius: f32, y: f32) -> impl Iterator<Item = Vec3> {
+    (0..sides).map(move |k| {
+        let angle = 2.0 * PI * k as f32 / sides as f32;
+        vec3(radius * angle.cos(), y, radius * angle.sin())
+    })
+}
+
+// Prism over a regular polygon with circumradius `radius`, axis along y
+pub fn prism_polyhedra(sides: usize, radius: f32, height: f32) -> (Vec<Vec3>, Vec<Vec<usize>>) {
+    assert!(sides >= 3);
+    let vertices = ring(sides, radius, -height / 2.0)
+        .chain(ring(sides, radius, height / 2.0))
+        .collect();
+
+    let mut faces = vec![(0..sides).rev().collect(), (sides..2 * sides).collect()];
+    for k in 0..sides {
+        let next = (k + 1) % sides;
+        faces.push(vec![next, next + sides, k + sides, k]);
+    }
+
+    (vertices, faces)
+}
+
+// Pyramid over a regular polygon with circumradius `radius`, apex up the y
+// axis. The centroid is at the origin, so the base sits at -height/4.
+pub fn pyramid_polyhedra(sides: usize, radius: f32, height: f32) -> (Vec<Vec3>, Vec<Vec<usize>>) {
+    assert!(sides >= 3);
+    let mut vertices: Vec<Vec3> = ring(sides, radius, -height / 4.0).collect();
+    vertices.push(vec3(0.0, height * 0.75, 0.0));
+
+    let mut faces = vec![(0..sides).rev().collect()];
+    for k in 0..sides {
+        faces.push(vec![(k + 1) % sides, sides, k]);
+    }
+
+    (vertices, faces)
+}
+
+pub fn cylinder_polyhedra(
+    radius: f32,
+    height: f32,
+    segments: usize,
+) -> (Vec<Vec3>, Vec<Vec<usize>>) {
+    prism_polyhedra(segments, radius, height)
+}
+
+pub fn cone_polyhedra(radius: f32, height: f32, segments: usize) -> (Vec<Vec3>, Vec<Vec<usize>>) {
+    pyramid_polyhedra(segments, radius, height)
+}
+
+// Sign combinations of the given points, scaled so that the edges are
+// `edgelen` long for a solid whose unscaled edges are `unit_edge` long
+fn platonic(points: &[Vec3], unit_edge: f32, edgelen: f32) -> (Vec<Vec3>, Vec<Vec<usize>>) {
+    let mut vertices: Vec<Vec3> = Vec::new();
+    for p in points {
+        for sx in [1.0, -1.0] {
+            for sy in [1.0, -1.0] {
+                for sz in [1.0, -1.0] {
+                    let v = vec3(p.x * sx, p.y * sy, p.z * sz) * (edgelen / unit_edge);
+                    if !vertices.contains(&v) {
+                        vertices.push(v);
+                    }
+                }
+            }
+        }
+    }
+
+    quickhull(&vertices).unwrap()
+}
+
+// Cyclic permutations of (x, y, z)
+fn cyclic(v: Vec3) -> [Vec3; 3] {
+    [v, vec3(v.y, v.z, v.x), vec3(v.z, v.x, v.y)]
+}
+
+pub fn tetrahedron_polyhedra(edgelen: f32) -> (Vec<Vec3>, Vec<Vec<usize>>) {
+    let scale = edgelen / (2.0 * 2.0f32.sqrt());
+    quickhull(&[
+        vec3(1.0, 1.0, 1.0) * scale,
+        vec3(1.0, -1.0, -1.0) * scale,
+        vec3(-1.0, 1.0, -1.0) * scale,
+        vec3(-1.0, -1.0, 1.0) * scale,
+    ])
+    .unwrap()
+}
+
+pub fn octahedron_polyhedra(edgelen: f32) -> (Vec<Vec3>, Vec<Vec<usize>>) {
+    platonic(&cyclic(vec3(1.0, 0.0, 0.0)), 2.0f32.sqrt(), edgelen)
+}
+
+pub fn icosahedron_polyhedra(edgelen: f32) -> (Vec<Vec3>, Vec<Vec<usize>>) {
+    let phi = (1.0 + 5.0f32.sqrt()) / 2.0;
+    platonic(&cyclic(vec3(0.0, 1.0, phi)), 2.0, edgelen)
+}
+
+pub fn dodecahedron_polyhedra(edgelen: f32) -> (Vec<Vec3>, Vec<Vec<usize>>) {
+    let phi = (1.0 + 5.0f32.sqrt()) / 2.0;
+    let mut points = cyclic(vec3(0.0, 1.0 / phi, phi)).to_vec();
+    points.push(vec3(1.0, 1.0, 1.0));
+    platonic(&points, 2.0 / phi, edgelen)
+}
+
+struct HullFace {
+    vertices: [usize; 3],
+    normal: Vec3,
+    offset: f32,
+    // Points still above this face
+    outside: Vec<usize>,
+    alive: bool,
+}
+
+impl HullFace {
+    fn new(vertices: [usize; 3], points: &[Vec3]) -> Self {
+        let [a, b, c] = vertices.map(|i| points[i]);
+        let normal = (b - a).cross(c - a).normalize();
+
+        Self {
+            vertices,
+            normal,
+            offset: normal.dot(a),
+            outside: Vec::new(),
+            alive: true,
+        }
+    }
+
+    fn distance(&self, p: Vec3) -> f32 {
+        self.normal.dot(p) - self.offset
+    }
+
+    fn edges(&self) -> [(usize, usize); 3] {
+        let [a, b, c] = self.vertices;
+        [(a, b), (b, c), (c, a)]
+    }
+}
+
+// Four points spanning a tetrahedron, wound so that its faces point outwards
+fn initial_simplex(points: &[Vec3], eps: f32) -> Option<[usize; 4]> {
+    let mut extremes = [0; 6];
+    for (i, p) in points.iter().enumerate() {
+        for axis in 0..3 {
+            if p[axis] < points[extremes[axis * 2]][axis] {
+                extremes[axis * 2] = i;
+            }
+            if p[axis] > points[extremes[axis * 2 + 1]][axis] {
+                extremes[axis * 2 + 1] = i;
+            }
+        }
+    }
+
+    let (mut a, mut b) = (0, 0);
+    for i in extremes {
+        for j in extremes {
+            if points[i].distance2(points[j]) > points[a].distance2(points[b]) {
+                (a, b) = (i, j);
+            }
+        }
+    }
+    if points[a].distance(points[b]) <= eps {
+        return None;
+    }
+
+    let ab = (points[b] - points[a]).normalize();
+    let line_distance = |i: &usize| (points[*i] - points[a]).cross(ab).magnitude();
+    let c = (0..points.len()).max_by(|i, j| line_distance(i).total_cmp(&line_distance(j)))?;
+    if line_distance(&c) <= eps {
+        return None;
+    }
+
+    let n = (points[b] - points[a])
+        .cross(points[c] - points[a])
+        .normalize();
+    let plane_distance = |i: &usize| n.dot(points[*i] - points[a]);
+    let d = (0..points.len())
+        .max_by(|i, j| plane_distance(i).abs().total_cmp(&plane_distance(j).abs()))?;
+    if plane_distance(&d).abs() <= eps {
+        return None;
+    }
+
+    if plane_distance(&d) > 0.0 {
+        Some([a, c, b, d])
+    } else {
+        Some([a, b, c, d])
+    }
+}
+
+// Indices of the faces seen from a point, and the directed edges of the horizon
+type VisibleRegion = (Vec<usize>, Vec<(usize, usize)>);
+
+// Faces seen from p, grown from the seed so the region stays connected, and
+// the horizon around them. Neighbors p is above by less than the tolerance are
+// taken too, or the new faces would fold back over them. None when a twin edge
+// is missing or the horizon isn't a single loop.
+fn visible_region(
+    faces: &[HullFace],
+    edges: &HashMap<(usize, usize), usize>,
+    seed: usize,
+    p: Vec3,
+) -> Option<VisibleRegion> {
+    let mut visible = vec![seed];
+    let mut stack = vec![seed];
+    while let Some(f) = stack.pop() {
+        for (u, v) in faces[f].edges() {
+            let neighbor = *edges.get(&(v, u))?;
+            if !visible.contains(&neighbor) && faces[neighbor].distance(p) > 0.0 {
+                visible.push(neighbor);
+                stack.push(neighbor);
+            }
+        }
+    }
+
+    let mut horizon: Vec<(usize, usize)> = Vec::new();
+    for &f in &visible {
+        for (u, v) in faces[f].edges() {
+            if !visible.contains(edges.get(&(v, u))?) {
+                if horizon.iter().any(|(w, _)| *w == u) {
+                    return None;
+                }
+                horizon.push((u, v));
+            }
+        }
+    }
+
+    Some((visible, horizon))
+}
+
+// Convex hull of a point cloud, in the format `PolyhedraCollider::new` takes.
+// Faces are wound like cube_polyhedra, coplanar triangles are merged into
+// convex polygons and vertices lying on an edge are dropped. Returns None when the
+// points don't span a volume, or when the hull can't be grown cleanly around a
+// point, rather than leaving that point outside.
+pub fn quickhull(points: &[Vec3]) -> Option<(Vec<Vec3>, Vec<Vec<usize>>)> {
+    if points.len() < 4 {
+        return None;
+    }
+    let extent = points.iter().fold(0.0f32, |m, p| {
+        m.max(p.x.abs()).max(p.y.abs()).max(p.z.abs())
+    });
+    let eps = extent.max(1.0) * 1e-5;
+
+    let [a, b, c, d] = initial_simplex(points, eps)?;
+    let mut faces: Vec<HullFace> = [[a, b, c], [a, d, b], [b, d, c], [c, d, a]]
+        .into_iter()
+        .map(|f| HullFace::new(f, points))
+        .collect();
+    // Directed edge -> the face it belongs to
+    let mut edges: HashMap<(usize, usize), usize> = HashMap::new();
+    for (i, face) in faces.iter().enumerate() {
+        for edge in face.edges() {
+            edges.insert(edge, i);
+        }
+    }
+
+    for (i, p) in points.iter().enumerate() {
+        if let Some(face) = faces.iter_mut().find(|f| f.distance(*p) > eps) {
+            face.outside.push(i);
+        }
+    }
+
+    while let Some(seed) = faces.iter().position(|f| f.alive && !f.outside.is_empty()) {
+        let eye = *faces[seed]
+            .outside
+            .iter()
+            .max_by(|i, j| {
+                let di = faces[seed].distance(points[**i]);
+                let dj = faces[seed].distance(points[**j]);
+                di.total_cmp(&dj)
+            })
+            .unwrap();
+        let (visible, horizon) = visible_region(&faces, &edges, seed, points[eye])?;
+
+        let mut orphans = Vec::new();
+        for &f in &visible {
+            faces[f].alive = false;
+            orphans.append(&mut faces[f].outside);
+            for edge in faces[f].edges() {
+                edges.remove(&edge);
+            }
+        }
+
+        let first = faces.len();
+        for (u, v) in horizon {
+            let face = HullFace::new([u, v, eye], points);
+            for edge in face.edges() {
+                edges.insert(edge, faces.len());
+            }
+            faces.push(face);
+        }
+
+        for i in orphans {
+            if i == eye {
+                continue;
+            }
+            if let Some(face) = faces[first..]
+                .iter_mut()
+                .find(|f| f.distance(points[i]) > eps)
+            {
+                face.outside.push(i);
+            }
+        }
+    }
+
+    let triangles: Vec<&HullFace> = faces.iter().filter(|f| f.alive).collect();
+    Some(merge_coplanar(points, &triangles, eps))
+}
+
+fn merge_coplanar(
+    points: &[Vec3],
+    triangles: &[&HullFace],
+    eps: f32,
+) -> (Vec<Vec3>, Vec<Vec<usize>>) {
+    let mut edges: HashMap<(usize, usize), usize> = HashMap::new();
+    for (i, t) in triangles.iter().enumerate() {
+        for edge in t.edges() {
+            edges.insert(edge, i);
+        }
+    }
+
+    // Flood fill groups of neighboring triangles lying in the plane of the
+    // group's first triangle
+    let mut group = vec![usize::MAX; triangles.len()];
+    let mut groups = 0;
+    for seed in 0..triangles.len() {
+        if group[seed] != usize::MAX {
+            continue;
+        }
+        group[seed] = groups;
+        let mut stack = vec![seed];
+        while let Some(t) = stack.pop() {
+            for (u, v) in triangles[t].edges() {
+                let Some(&neighbor) = edges.get(&(v, u)) else {
+                    continue;
+                };
+                let coplanar = triangles[neighbor].normal.dot(triangles[seed].normal) > 1.0 - 1e-4
+                    && triangles[neighbor]
+                        .vertices
+                        .iter()
+                        .all(|i| triangles[seed].distance(points[*i]).abs() <= eps * 10.0);
+                if group[neighbor] == usize::MAX && coplanar {
+                    group[neighbor] = groups;
+                    stack.push(neighbor);
+                }
+            }
+        }
+        groups += 1;
+    }
+
+    let mut polygons: Vec<Vec<usize>> = Vec::new();
+    for g in 0..groups {
+        let members: Vec<usize> = (0..triangles.len()).filter(|t| group[*t] == g).collect();
+
+        // Boundary edges are the ones whose twin lies outside the group
+        let mut next: HashMap<usize, usize> = HashMap::new();
+        let mut boundary = 0;
+        for &t in &members {
+            for (u, v) in triangles[t].edges() {
+                if edges.get(&(v, u)).is_none_or(|t| group[*t] != g) {
+                    next.insert(u, v);
+                    boundary += 1;
+                }
+            }
+        }
+
+        let start = triangles[members[0]]
+            .vertices
+            .into_iter()
+            .find(|v| next.contains_key(v));
+        let mut polygon = Vec::new();
+        if let Some(start) = start {
+            let mut v = start;
+            while polygon.len() <= boundary {
+                polygon.push(v);
+                v = next[&v];
+                if v == start {
+                    break;
+                }
+            }
+        }
+
+        // Triangles merged within the tolerance can still bend the boundary
+        // inwards, which would flip the normal taken from the polygon
+        let normal = triangles[members[0]].normal;
+        let n = polygon.len();
+        let convex = (0..n).all(|k| {
+            let u = points[polygon[k]];
+            let v = points[polygon[(k + 1) % n]];
+            let w = points[polygon[(k + 2) % n]];
+            (v - u).cross(w - v).dot(normal) >= -eps * (w - u).magnitude()
+        });
+
+        if next.len() != boundary || polygon.len() != boundary || !convex {
+            // Pinched or bent boundary, keep the triangles as they are
+            polygons.extend(members.iter().map(|t| triangles[*t].vertices.to_vec()));
+        } else {
+            polygons.push(polygon);
+        }
+    }
+
+    // Drop vertices sitting on a straight edge. Such a vertex is on the
+    // boundary of both faces sharing the edge, so it goes from both of them.
+    for polygon in &mut polygons {
+        let mut i = 0;
+        while polygon.len() > 3 && i < polygon.len() {
+            let n = polygon.len();
+            let u = points[polygon[(i + n - 1) % n]];
+            let v = points[polygon[i]];
+            let w = points[polygon[(i + 1) % n]];
+            if (v - u).cross(w - u).magnitude() <= eps * (w - u).magnitude() {
+                polygon.remove(i);
+            } else {
+                i += 1;
+            }
+        }
+
+        // The hull is built with outward CCW faces, while the clipping code
+        // expects ccw_normal to point inwards
+        polygon.reverse();
+
+        // Face normals come from the first three vertices, so start at the
+        // corner spanning the largest triangle
+        let n = polygon.len();
+        let area = |i: usize| {
+            let u = points[polygon[i]];
+            (points[polygon[(i + 1) % n]] - u)
+                .cross(points[polygon[(i + 2) % n]] - u)
+                .magnitude2()
+        };
+        let start = (0..n).max_by(|i, j| area(*i).total_cmp(&area(*j))).unwrap();
+        polygon.rotate_left(start);
+    }
+
+    let mut index = HashMap::new();
+    let mut vertices = Vec::new();
+    let faces = polygons
+        .into_iter()
+        .map(|polygon| {
+            polygon
+                .into_iter()
+                .map(|v| {
+                    *index.entry(v).or_insert_with(|| {
+                        vertices.push(points[v]);
+                        vertices.len() - 1
+                    })
+                })
+                .collect()
+        })
+        .collect();
+
+    (vertices, faces)
+}
+
+#[cfg(test)]
+mod chull_test {
+    use super::*;
+    use crate::collision::collider::ccw_normal;
+
+    // Every input point lies inside the hull and every face is wound like
+    // cube_polyhedra, facing away from the hull's center
+    fn check_hull(points: &[Vec3], hull: &(Vec<Vec3>, Vec<Vec<usize>>)) {
+        let (vertices, faces) = hull;
+        let center = vertices.iter().sum::<Vec3>() / vertices.len() as f32;
+        for face in faces {
+            assert!(face.len() >= 3);
+            let n = -ccw_normal(face, vertices).normalize();
+            assert!(n.dot(vertices[face[0]] - center) > 0.0);
+            for v in face {
+                assert!(n.dot(vertices[*v] - vertices[face[0]]).abs() < 5e-4);
+            }
+            for p in points {
+                assert!(n.dot(p - vertices[face[0]]) < 1e-4);
+            }
+        }
+    }
+
+    #[test]
+    fn coplanar_cloud() {
+        // Dense grids on the faces of a cube, every point given twice and the
+        // corners many times over, so that most points are coplanar with
+        // faces of the hull, or sit exactly on its edges
+        let mut points = Vec::new();
+        for axis in 0..3 {
+            for side in [-1.0, 1.0] {
+                for i in 0..=20 {
+                    for j in 0..=20 {
+                        let u = i as f32 / 10.0 - 1.0;
+                        let v = j as f32 / 10.0 - 1.0;
+                        let p = match axis {
+                            0 => vec3(side, u, v),
+                            1 => vec3(u, side, v),
+                            _ => vec3(u, v, side),
+                        };
+                        points.push(p);
+                        points.push(p + vec3(1e-7, -1e-7, 1e-7));
+                    }
+                }
+            }
+        }
+
+        let hull = quickhull(&points).unwrap();
+        assert_eq!(hull.0.len(), 8);
+        assert_eq!(hull.1.len(), 6);
+        check_hull(&points, &hull);
+    }
+
+    #[test]
+    fn cube_cloud() {
+        let mut points = cube_polyhedra(2.0).0;
+        for i in 0..200 {
+            let f = i as f32;
+            points.push(vec3((f * 0.37).sin(), (f * 1.13).cos(), (f * 2.71).sin()));
+        }
+        // Edge midpoints and face centers don't make it into the hull
+        points.push(vec3(1.0, 1.0, 0.0));
+        points.push(vec3(0.0, -1.0, 1.0));
+        points.push(vec3(0.0, 0.0, -1.0));
+
+        let hull = quickhull(&points).unwrap();
+        assert_eq!(hull.0.len(), 8);
+        assert_eq!(hull.1.len(), 6);
+        assert!(hull.1.iter().all(|f| f.len() == 4));
+        check_hull(&points, &hull);
+        check_hull(&points, &cube_polyhedra(2.0));
+    }
+
+    #[test]
+    fn sphere_cloud() {
+        let points: Vec<Vec3> = (0..300)
+            .map(|i| {
+                let f = i as f32;
+                vec3((f * 0.91).sin(), (f * 0.53).cos(), (f * 1.7).sin()).normalize() * 2.0
+            })
+            .collect();
+
+        let hull = quickhull(&points).unwrap();
+        check_hull(&points, &hull);
+
+        // Euler characteristic of a closed convex surface
+        let edges: usize = hull.1.iter().map(|f| f.len()).sum::<usize>() / 2;
+        assert_eq!(hull.0.len() + hull.1.len(), edges + 2);
+    }
+
+    #[test]
+    fn degenerate_cloud() {
+        let flat: Vec<Vec3> = (0..10)
+            .map(|i| vec3(i as f32, (i * i) as f32, 0.0))
+            .collect();
+        assert!(quickhull(&flat).is_none());
+        assert!(quickhull(&cube_polyhedra(1.0).0[..3]).is_none());
+    }
+
+    #[test]
+    fn near_coplanar_cloud() {
+        // Points scattered around the faces of a cube by up to ten times the
+        // hull's tolerance, each given several times over
+        for seed in 0..24 {
+            let scale = [1e-6, 5e-6, 1e-5, 2e-5, 5e-5, 1e-4][seed % 6];
+            let mut points = cube_polyhedra(2.0).0;
+            for i in 0..300 {
+                let f = (i + seed * 300) as f32;
+                let jitter = scale * (f * 7.3).sin();
+                let u = (f * 0.37).sin();
+                let v = (f * 1.13).cos();
+                let p = match i % 3 {
+                    0 => vec3(1.0 + jitter, u, v),
+                    1 => vec3(u, -1.0 - jitter, v),
+                    _ => vec3(u, v, 1.0 + jitter),
+                };
+                for _ in 0..3 {
+                    points.push(p);
+                }
+            }
+
+            let hull = quickhull(&points).unwrap();
+            check_hull(&points, &hull);
+        }
+
+        let corners: Vec<Vec3> = cube_polyhedra(2.0).0.repeat(10);
+        let hull = quickhull(&corners).unwrap();
+        assert_eq!(hull.0.len(), 8);
+        assert_eq!(hull.1.len(), 6);
+        check_hull(&corners, &hull);
+    }
+
+    #[test]
+    fn primitives() {
+        let shapes = [
+            (box_polyhedra(vec3(0.5, 1.0, 2.0)), 6),
+            (prism_polyhedra(6, 1.0, 2.0), 8),
+            (pyramid_polyhedra(4, 1.0, 2.0), 5),
+            (cylinder_polyhedra(0.5, 1.0, 16), 18),
+            (cone_polyhedra(0.5, 1.0, 16), 17),
+            (tetrahedron_polyhedra(1.0), 4),
+            (octahedron_polyhedra(1.0), 8),
+            (icosahedron_polyhedra(1.0), 20),
+            (dodecahedron_polyhedra(1.0), 12),
+        ];
+        for (shape, faces) in &shapes {
+            assert_eq!(shape.1.len(), *faces);
+            check_hull(&shape.0, shape);
+        }
+
+        // Platonic solids have every edge `edgelen` long
+        for (shape, _) in &shapes[5..] {
+            let (vertices, faces) = shape;
+            for face in faces {
+                for k in 0..face.len() {
+                    let edge = vertices[face[k]] - vertices[face[(k + 1) % face.len()]];
+                    assert!((edge.magnitude() - 1.0).abs() < 1e-4);
+                }
+            }
+        }
+    }
+}
@@ -1,11 +1,17 @@
 use three_d::*;
 use crate::body::*;
+use crate::cube::*;
+use crate::inertiatensor::*;
+use super::aabb::*;
+use super::gjk::gjk_raycast;
+use super::raycast::*;
+use crate::material::Material;
 use std::rc::Weak;
 use std::cell::RefCell;
 use std::vec::Vec;
 use std::rc::Rc;
 
-pub fn ccw_normal(face: &Vec<usize>, vertices: &Vec<Vec3>) -> Vec3 {
+pub fn ccw_normal(face: &[usize], vertices: &[Vec3]) -> Vec3 {
     (vertices[face[1]] - vertices[face[0]]).cross(vertices[face[2]] - vertices[face[0]])
 }
 
@@ -13,6 +19,9 @@ pub trait Collider {
     fn support(&self, dir: Vec3) -> Point3<f32>; 
     fn to_local(&self, pos: Point3<f32>) -> Vec3;
     fn get_body(&self) -> Rc<RefCell<dyn Body>>;
+    fn material(&self) -> Material {
+        Material::default()
+    }
 
     fn get_vertices(&self) -> Option<Vec<Vec3>> {
         None
@@ -20,28 +29,184 @@ pub trait Collider {
     fn get_faces(&self) -> Option<&Vec<Vec<usize>>> {
         None
     }
+    // World-space center and radius
+    fn get_sphere(&self) -> Option<(Vec3, f32)> {
+        None
+    }
+    // World-space segment end points and radius
+    fn get_capsule(&self) -> Option<(Vec3, Vec3, f32)> {
+        None
+    }
+    // Unit normal and offset, the solid side is normal.dot(x) <= offset
+    fn get_plane(&self) -> Option<(Vec3, f32)> {
+        None
+    }
+    // Concave colliders: world-space triangles overlapping `aabb`
+    fn get_triangles(&self, _aabb: &Aabb) -> Option<Vec<[Vec3; 3]>> {
+        None
+    }
+    // True for colliders made of get_triangles, which are always static
+    fn is_concave(&self) -> bool {
+        false
+    }
+
+    // World-space bounding box
+    fn aabb(&self) -> Aabb {
+        Aabb::from_collider(self)
+    }
+
+    // World-space (center, radius)
+    fn bounding_sphere(&self) -> (Vec3, f32) {
+        let aabb = self.aabb();
+        (aabb.center(), (aabb.max - aabb.min).magnitude() * 0.5)
+    }
+
+    // Bounding box covering the motion of the body over `dt` at its current
+    // linear and angular velocity.
+    fn swept_aabb(&self, dt: f32) -> Aabb {
+        let body = self.get_body();
+        let (vel, avel, pos) = {
+            let body = body.as_ref().borrow();
+            (body.vel(), body.avel(), body.pos())
+        };
+        let (center, radius) = self.bounding_sphere();
+
+        self.aabb()
+            .swept(vel * dt)
+            .expand(avel.magnitude() * dt * (radius + center.distance(pos)))
+    }
+
+    // First hit of the ray origin + dir*t, 0 <= t <= max_t. Concave colliders
+    // are tested triangle by triangle, convex ones through GJK.
+    fn cast_ray(&self, origin: Vec3, dir: Vec3, max_t: f32) -> Option<RayHit> {
+        let bounds = Aabb::from_points(&[origin, origin + dir * max_t]);
+        match self.get_triangles(&bounds) {
+            Some(triangles) => ray_triangles(origin, dir, max_t, &triangles),
+            None => gjk_raycast(self, origin, dir, max_t),
+        }
+    }
 }
 
 #[derive(Clone)]
 pub struct SphereCollider {
     parent: Weak<RefCell<dyn Body>>,
-    radius: f32
+    radius: f32,
+    offset: Vec3,
+    material: Material,
 }
 
 impl SphereCollider {
     pub fn new(parent: &Rc<RefCell<dyn Body>>, radius: f32) -> Self {
         Self {
             radius,
-            parent: Rc::downgrade(parent)
+            parent: Rc::downgrade(parent),
+            offset: Vec3::zero(),
+            material: Material::default(),
         }
     }
-} 
+
+    // Center placed at `offset` in the parent's frame
+    pub fn with_offset(mut self, offset: Vec3) -> Self {
+        self.offset = offset;
+        self
+    }
+
+    pub fn with_material(mut self, material: Material) -> Self {
+        self.material = material;
+        self
+    }
+
+    fn center(&self) -> Vec3 {
+        let parent = self.parent.upgrade().unwrap();
+        let parent = parent.as_ref().borrow();
+        parent.pos() + parent.apos().rotate_vector(self.offset)
+    }
+}
 
 impl Collider for SphereCollider {
     fn support(&self, dir: Vec3) -> Point3<f32> {
-        let pos: Point3<f32> = Point3::origin() + self.parent.upgrade().unwrap().as_ref().borrow().pos();
+        Point3::origin() + self.center() + dir.normalize() * self.radius
+    }
+    fn to_local(&self, x: Point3<f32>) -> Vec3 {
+        let parent = self.parent.upgrade().unwrap();
+        let pos = parent.as_ref().borrow().pos();
+        let apos = parent.as_ref().borrow().apos();
+        apos.invert().rotate_vector(x.to_vec() - pos)
+    }
+    fn get_body(&self) -> Rc<RefCell<dyn Body>> {
+        self.parent.upgrade().unwrap()
+    }
+    fn material(&self) -> Material {
+        self.material
+    }
+    fn get_sphere(&self) -> Option<(Vec3, f32)> {
+        Some((self.center(), self.radius))
+    }
+
+    fn aabb(&self) -> Aabb {
+        let center = self.center();
+        Aabb::new(center, center).expand(self.radius)
+    }
+    fn bounding_sphere(&self) -> (Vec3, f32) {
+        (self.center(), self.radius)
+    }
+    fn cast_ray(&self, origin: Vec3, dir: Vec3, max_t: f32) -> Option<RayHit> {
+        ray_sphere(origin, dir, max_t, self.center(), self.radius)
+    }
+}
+
+// Segment along the local y axis from -half_height to half_height, swept by radius
+#[derive(Clone)]
+pub struct CapsuleCollider {
+    parent: Weak<RefCell<dyn Body>>,
+    half_height: f32,
+    radius: f32,
+    offset: Vec3,
+    rotation: Quat,
+    material: Material,
+}
+
+impl CapsuleCollider {
+    pub fn new(parent: &Rc<RefCell<dyn Body>>, half_height: f32, radius: f32) -> Self {
+        Self {
+            parent: Rc::downgrade(parent),
+            half_height,
+            radius,
+            offset: Vec3::zero(),
+            rotation: Quat::one(),
+            material: Material::default(),
+        }
+    }
+
+    // Segment centered at `offset` and turned by `rotation` in the parent's frame
+    pub fn with_offset(mut self, offset: Vec3, rotation: Quat) -> Self {
+        self.offset = offset;
+        self.rotation = rotation;
+        self
+    }
+
+    pub fn with_material(mut self, material: Material) -> Self {
+        self.material = material;
+        self
+    }
+
+    fn segment(&self) -> (Vec3, Vec3) {
+        let parent = self.parent.upgrade().unwrap();
+        let parent = parent.as_ref().borrow();
+        let center = parent.pos() + parent.apos().rotate_vector(self.offset);
+        let axis = (parent.apos() * self.rotation)
+            .rotate_vector(Vec3::unit_y() * self.half_height);
+
+        (center - axis, center + axis)
+    }
+}
+
+impl Collider for CapsuleCollider {
+    fn support(&self, dir: Vec3) -> Point3<f32> {
+        let (a, b) = self.segment();
+        let end = if (b - a).dot(dir) > 0.0 { b } else { a };
 
-        pos + dir.normalize() * self.radius
+        Point3::origin() + end + dir.normalize() * self.radius
     }
     fn to_local(&self, x: Point3<f32>) -> Vec3 {
         let parent = self.parent.upgrade().unwrap();
@@ -52,6 +217,106 @@ impl Collider for SphereCollider {
     fn get_body(&self) -> Rc<RefCell<dyn Body>> {
         self.parent.upgrade().unwrap()
     }
+    fn material(&self) -> Material {
+        self.material
+    }
+    fn get_capsule(&self) -> Option<(Vec3, Vec3, f32)> {
+        let (a, b) = self.segment();
+        Some((a, b, self.radius))
+    }
+
+    fn aabb(&self) -> Aabb {
+        let (a, b) = self.segment();
+        Aabb::from_points(&[a, b]).expand(self.radius)
+    }
+    fn bounding_sphere(&self) -> (Vec3, f32) {
+        let (a, b) = self.segment();
+        ((a + b) / 2.0, self.half_height + self.radius)
+    }
+}
+
+// Half extent used wherever an infinite plane needs finite bounds
+pub const PLANE_EXTENT: f32 = 1.0e4;
+
+// Static half-space normal.dot(x) <= offset. It owns an immovable body of its
+// own, so it doesn't have to be added to `Physics` as a body.
+#[derive(Clone)]
+pub struct PlaneCollider {
+    body: Rc<RefCell<dyn Body>>,
+    normal: Vec3,
+    offset: f32,
+    material: Material,
+}
+
+impl PlaneCollider {
+    pub fn new(normal: Vec3, offset: f32) -> Self {
+        let normal = normal.normalize();
+        Self {
+            body: Rc::new(RefCell::new(RigidBody::new(
+                normal * offset,
+                Quat::one(),
+                0.0,
+                zeroinertia_mass(),
+            ))),
+            normal,
+            offset,
+            material: Material::default(),
+        }
+    }
+
+    pub fn with_material(mut self, material: Material) -> Self {
+        self.material = material;
+        self
+    }
+}
+
+impl Collider for PlaneCollider {
+    fn support(&self, dir: Vec3) -> Point3<f32> {
+        let tangent = dir - self.normal * self.normal.dot(dir);
+        let mut p = self.normal * self.offset;
+        if tangent.magnitude2() > f32::EPSILON {
+            p += tangent.normalize() * PLANE_EXTENT;
+        }
+        if self.normal.dot(dir) < 0.0 {
+            p -= self.normal * PLANE_EXTENT;
+        }
+
+        Point3::origin() + p
+    }
+    fn to_local(&self, x: Point3<f32>) -> Vec3 {
+        self.body.as_ref().borrow().to_local(x.to_vec())
+    }
+    fn get_body(&self) -> Rc<RefCell<dyn Body>> {
+        self.body.clone()
+    }
+    fn material(&self) -> Material {
+        self.material
+    }
+    fn get_plane(&self) -> Option<(Vec3, f32)> {
+        Some((self.normal, self.offset))
+    }
+
+    fn aabb(&self) -> Aabb {
+        let extent = vec3(PLANE_EXTENT, PLANE_EXTENT, PLANE_EXTENT);
+        let mut aabb = Aabb::new(-extent, extent);
+        // An axis aligned plane is bounded on one side
+        for axis in 0..3 {
+            if (self.normal[axis].abs() - 1.0).abs() < f32::EPSILON {
+                if self.normal[axis] > 0.0 {
+                    aabb.max[axis] = self.offset;
+                } else {
+                    aabb.min[axis] = -self.offset;
+                }
+            }
+        }
+        aabb
+    }
+    fn bounding_sphere(&self) -> (Vec3, f32) {
+        (self.normal * self.offset, PLANE_EXTENT * 3.0f32.sqrt())
+    }
+    fn cast_ray(&self, origin: Vec3, dir: Vec3, max_t: f32) -> Option<RayHit> {
+        ray_plane(origin, dir, max_t, self.normal, self.offset)
+    }
 }
 
 #[derive(Clone)]
@@ -59,15 +324,36 @@ pub struct PolyhedraCollider {
     parent: Weak<RefCell<dyn Body>>,
     vertices: Vec<Vec3>,
     faces: Vec<Vec<usize>>,
+    radius: f32,
+    material: Material,
 }
 
 impl PolyhedraCollider {
     pub fn new(parent: &Rc<RefCell<dyn Body>>, polyhedra: (Vec<Vec3>, Vec<Vec<usize>>)) -> Self {
+        let radius = polyhedra.0.iter().map(|v| v.magnitude()).fold(0.0, f32::max);
+
         Self {
             parent: Rc::downgrade(parent),
             vertices: polyhedra.0,
-            faces: polyhedra.1
+            faces: polyhedra.1,
+            radius,
+            material: Material::default(),
+        }
+    }
+
+    // Moves the shape to `offset` and turns it by `rotation` in the parent's
+    // frame, so that several of them can make up one body
+    pub fn with_offset(mut self, offset: Vec3, rotation: Quat) -> Self {
+        for v in &mut self.vertices {
+            *v = rotation.rotate_vector(*v) + offset;
         }
+        self.radius = self.vertices.iter().map(|v| v.magnitude()).fold(0.0, f32::max);
+        self
+    }
+
+    pub fn with_material(mut self, material: Material) -> Self {
+        self.material = material;
+        self
     }
 }
 
@@ -99,6 +385,9 @@ impl Collider for PolyhedraCollider {
     fn get_body(&self) -> Rc<RefCell<dyn Body>> {
         self.parent.upgrade().unwrap()
     }
+    fn material(&self) -> Material {
+        self.material
+    }
 
     fn get_vertices(&self) -> Option<Vec<Vec3>> {
         let parent = self.parent.upgrade().unwrap().clone();
@@ -115,8 +404,19 @@ impl Collider for PolyhedraCollider {
     fn get_faces(&self) -> Option<&Vec<Vec<usize>>> {
         Some(&self.faces)
     }
+
+    fn aabb(&self) -> Aabb {
+        Aabb::from_points(&self.get_vertices().unwrap())
+    }
+    fn bounding_sphere(&self) -> (Vec3, f32) {
+        let pos = self.parent.upgrade().unwrap().as_ref().borrow().pos();
+        (pos, self.radius)
+    }
+    fn cast_ray(&self, origin: Vec3, dir: Vec3, max_t: f32) -> Option<RayHit> {
+        ray_polyhedra(origin, dir, max_t, &self.get_vertices().unwrap(), &self.faces)
+    }
 }
 
-pub fn support(a: &Box<dyn Collider>, b: &Box<dyn Collider>, dir: Vec3) -> Point3<f32> {
+pub fn support(a: &dyn Collider, b: &dyn Collider, dir: Vec3) -> Point3<f32> {
     Point3::origin() + (a.support(dir) - b.support(-dir))
 }
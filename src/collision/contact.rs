@@ -0,0 +1,1006 @@
+use super::clipping::*;
+use super::collider::*;
+use super::geometry::*;
+use super::gjk::*;
+use super::trimesh::*;
+use itertools::Itertools;
+use std::collections::HashMap;
+use std::vec::Vec;
+use three_d::*;
+
+// Identifies which features of the two colliders (vertices, edges, faces)
+// made a contact, so that the same contact can be found again next step.
+pub type ContactFeature = u32;
+
+// Combines feature indices into one id (FNV-1a)
+pub fn contact_feature(parts: &[usize]) -> ContactFeature {
+    let hash = parts.iter().fold(0xcbf29ce484222325u64, |hash, part| {
+        (hash ^ *part as u64).wrapping_mul(0x100000001b3)
+    });
+    (hash ^ (hash >> 32)) as ContactFeature
+}
+
+// Contact points between two colliders a and b. `normal` points from a to b,
+// each contact is (point on a, point on b) and its depth is
+// (point on a - point on b).dot(normal).
+#[derive(Debug, Clone)]
+pub struct Manifold {
+    pub normal: Vec3,
+    pub contacts: Vec<(Vec3, Vec3)>,
+    pub depths: Vec<f32>,
+    pub features: Vec<ContactFeature>,
+}
+
+impl Manifold {
+    // Same manifold seen from b
+    pub fn flip(self) -> Self {
+        Self {
+            normal: -self.normal,
+            contacts: self.contacts.into_iter().map(|(a, b)| (b, a)).collect(),
+            depths: self.depths,
+            features: self.features,
+        }
+    }
+
+    // Mixes `tag` into every feature, for manifolds coming from one of many
+    // parts of the same collider
+    pub fn tagged(self, tag: ContactFeature) -> Self {
+        Self {
+            features: self
+                .features
+                .iter()
+                .map(|f| contact_feature(&[*f as usize, tag as usize]))
+                .collect(),
+            ..self
+        }
+    }
+
+    // At most MAX_CONTACTS points: the deepest one, the one furthest from it,
+    // then the ones spanning the largest area with those, all measured in the
+    // contact plane. None if there are no contacts at all.
+    pub fn reduce(self) -> Option<Self> {
+        if self.contacts.is_empty() {
+            return None;
+        }
+        if self.contacts.len() <= MAX_CONTACTS {
+            return Some(self);
+        }
+
+        let normal = self.normal;
+        let points: Vec<Vec3> = self.contacts.iter().map(|(_, pb)| *pb).collect();
+        let area = |p: Vec3, q: Vec3, r: Vec3| (q - p).cross(r - p).dot(normal) / 2.0;
+        let best = |score: &dyn Fn(usize) -> f32| {
+            (0..points.len())
+                .max_by(|i, j| score(*i).total_cmp(&score(*j)))
+                .unwrap()
+        };
+
+        let first = best(&|i| self.depths[i]);
+        let second = best(&|i| {
+            let d = points[i] - points[first];
+            (d - normal * d.dot(normal)).magnitude2()
+        });
+        let third = best(&|i| area(points[first], points[second], points[i]).abs());
+
+        // Keep the triangle counterclockwise about the normal, then add the
+        // point furthest outside any of its edges
+        let triangle = if area(points[first], points[second], points[third]) >= 0.0 {
+            [first, second, third]
+        } else {
+            [first, third, second]
+        };
+        let fourth = best(&|i| {
+            (0..3)
+                .map(|k| {
+                    -area(
+                        points[triangle[k]],
+                        points[triangle[(k + 1) % 3]],
+                        points[i],
+                    )
+                })
+                .fold(0.0, f32::max)
+        });
+
+        let mut kept = vec![first, second, third, fourth];
+        kept.sort();
+        kept.dedup();
+        Some(Self {
+            normal,
+            contacts: kept.iter().map(|i| self.contacts[*i]).collect(),
+            depths: kept.iter().map(|i| self.depths[*i]).collect(),
+            features: kept.iter().map(|i| self.features[*i]).collect(),
+        })
+    }
+}
+
+// Most contact points a manifold is reduced to
+pub const MAX_CONTACTS: usize = 4;
+
+enum Shape<'a> {
+    Plane(Vec3, f32),
+    Sphere(Vec3, f32),
+    Capsule(Vec3, Vec3, f32),
+    Polyhedra(Vec<Vec3>, &'a Vec<Vec<usize>>),
+}
+
+impl Shape<'_> {
+    // Pairs are handled with the lower ranked shape as a
+    fn rank(&self) -> usize {
+        match self {
+            Shape::Plane(..) => 0,
+            Shape::Sphere(..) => 1,
+            Shape::Capsule(..) => 2,
+            Shape::Polyhedra(..) => 3,
+        }
+    }
+}
+
+fn shape_of(collider: &dyn Collider) -> Option<Shape<'_>> {
+    if let Some((normal, offset)) = collider.get_plane() {
+        return Some(Shape::Plane(normal, offset));
+    }
+    if let Some((center, radius)) = collider.get_sphere() {
+        return Some(Shape::Sphere(center, radius));
+    }
+    if let Some((a, b, radius)) = collider.get_capsule() {
+        return Some(Shape::Capsule(a, b, radius));
+    }
+    Some(Shape::Polyhedra(
+        collider.get_vertices()?,
+        collider.get_faces()?,
+    ))
+}
+
+// One manifold per convex pair. Concave colliders contribute one manifold per
+// triangle overlapping the other collider.
+pub fn generate_manifolds(a: &dyn Collider, b: &dyn Collider) -> Vec<Manifold> {
+    let (concave, convex, flipped) = if let Some(triangles) = a.get_triangles(&b.aabb()) {
+        (triangles, b, false)
+    } else if let Some(triangles) = b.get_triangles(&a.aabb()) {
+        (triangles, a, true)
+    } else {
+        return generate_contacts(a, b).into_iter().collect();
+    };
+    let body = if flipped { b.get_body() } else { a.get_body() };
+
+    if convex.is_concave() || convex.get_plane().is_some() {
+        // static against static
+        return Vec::new();
+    }
+
+    concave
+        .into_iter()
+        .filter(|[p, q, r]| (q - p).cross(r - p).magnitude2() > f32::EPSILON)
+        .filter_map(|triangle| {
+            let tag = contact_feature(
+                &triangle
+                    .iter()
+                    .flat_map(|v| [v.x, v.y, v.z])
+                    .map(|x| x.to_bits() as usize)
+                    .collect::<Vec<usize>>(),
+            );
+            let prism = triangle_prism(&body, triangle);
+            if flipped {
+                generate_contacts(convex, &prism)
+            } else {
+                generate_contacts(&prism, convex)
+            }
+            .map(|m| m.tagged(tag))
+        })
+        .collect()
+}
+
+pub fn generate_contacts(a: &dyn Collider, b: &dyn Collider) -> Option<Manifold> {
+    let shape_a = shape_of(a)?;
+    let shape_b = shape_of(b)?;
+
+    if shape_a.rank() > shape_b.rank() {
+        return contacts_between(b, a, shape_b, shape_a)
+            .map(Manifold::flip)
+            .and_then(Manifold::reduce);
+    }
+    contacts_between(a, b, shape_a, shape_b).and_then(Manifold::reduce)
+}
+
+fn contacts_between(
+    a: &dyn Collider,
+    b: &dyn Collider,
+    shape_a: Shape,
+    shape_b: Shape,
+) -> Option<Manifold> {
+    match (shape_a, shape_b) {
+        (Shape::Plane(..), Shape::Plane(..)) => None,
+        (Shape::Plane(n, offset), Shape::Sphere(c, r)) => plane_sphere(n, offset, c, r),
+        (Shape::Plane(n, offset), Shape::Capsule(p, q, r)) => plane_capsule(n, offset, p, q, r),
+        (Shape::Plane(n, offset), Shape::Polyhedra(vertices, _)) => {
+            plane_polyhedra(n, offset, &vertices)
+        }
+        (Shape::Sphere(ca, ra), Shape::Sphere(cb, rb)) => sphere_sphere(ca, ra, cb, rb),
+        (Shape::Sphere(c, r), Shape::Capsule(p, q, rc)) => {
+            capsule_sphere(p, q, rc, c, r).map(Manifold::flip)
+        }
+        (Shape::Sphere(c, r), Shape::Polyhedra(vertices, faces)) => {
+            sphere_polyhedra(c, r, &vertices, faces)
+        }
+        (Shape::Capsule(p1, q1, r1), Shape::Capsule(p2, q2, r2)) => {
+            capsule_capsule(p1, q1, r1, p2, q2, r2)
+        }
+        (Shape::Capsule(p, q, r), Shape::Polyhedra(vertices, faces)) => {
+            capsule_polyhedra(p, q, r, &vertices, faces)
+        }
+        (Shape::Polyhedra(..), Shape::Polyhedra(..)) => polyhedra_polyhedra(a, b),
+        _ => unreachable!("shapes are ordered by rank"),
+    }
+}
+
+// Plane (solid below n.dot(x) = offset) as a. Every vertex of b below the
+// plane becomes a contact, no GJK/EPA involved.
+pub fn plane_polyhedra(normal: Vec3, offset: f32, vertices: &[Vec3]) -> Option<Manifold> {
+    let mut contacts = Vec::new();
+    let mut depths = Vec::new();
+    let mut features = Vec::new();
+    for (k, v) in vertices.iter().enumerate() {
+        let depth = offset - normal.dot(*v);
+        if depth >= 0.0 {
+            contacts.push((v + normal * depth, *v));
+            depths.push(depth);
+            features.push(k as ContactFeature);
+        }
+    }
+
+    if contacts.is_empty() {
+        return None;
+    }
+    Some(Manifold {
+        normal,
+        contacts,
+        depths,
+        features,
+    })
+}
+
+pub fn plane_sphere(normal: Vec3, offset: f32, center: Vec3, radius: f32) -> Option<Manifold> {
+    let dist = normal.dot(center) - offset;
+    if dist > radius {
+        return None;
+    }
+
+    Some(Manifold {
+        normal,
+        contacts: vec![(center - normal * dist, center - normal * radius)],
+        depths: vec![radius - dist],
+        features: vec![0],
+    })
+}
+
+pub fn plane_capsule(normal: Vec3, offset: f32, p: Vec3, q: Vec3, radius: f32) -> Option<Manifold> {
+    let mut contacts = Vec::new();
+    let mut depths = Vec::new();
+    let mut features = Vec::new();
+    for (k, m) in [p, q]
+        .into_iter()
+        .enumerate()
+        .filter_map(|(k, c)| Some((k, plane_sphere(normal, offset, c, radius)?)))
+    {
+        contacts.extend(m.contacts);
+        depths.extend(m.depths);
+        features.push(k as ContactFeature);
+    }
+
+    if contacts.is_empty() {
+        return None;
+    }
+    Some(Manifold {
+        normal,
+        contacts,
+        depths,
+        features,
+    })
+}
+
+pub fn sphere_sphere(ca: Vec3, ra: f32, cb: Vec3, rb: f32) -> Option<Manifold> {
+    let diff = cb - ca;
+    let dist = diff.magnitude();
+    if dist > ra + rb {
+        return None;
+    }
+
+    let normal = if dist > f32::EPSILON {
+        diff / dist
+    } else {
+        Vec3::unit_y()
+    };
+
+    Some(Manifold {
+        normal,
+        contacts: vec![(ca + normal * ra, cb - normal * rb)],
+        depths: vec![ra + rb - dist],
+        features: vec![0],
+    })
+}
+
+// Sphere as a, polyhedron (world-space vertices) as b
+pub fn sphere_polyhedra(
+    center: Vec3,
+    radius: f32,
+    vertices: &[Vec3],
+    faces: &[Vec<usize>],
+) -> Option<Manifold> {
+    let (closest, outward, dist) = polyhedra_closest_point(vertices, faces, center);
+    if dist > radius {
+        return None;
+    }
+
+    let normal = -outward;
+    Some(Manifold {
+        normal,
+        contacts: vec![(center + normal * radius, closest)],
+        depths: vec![radius - dist],
+        features: vec![0],
+    })
+}
+
+// Capsule (segment pq) as a, sphere as b
+pub fn capsule_sphere(p: Vec3, q: Vec3, rc: f32, center: Vec3, radius: f32) -> Option<Manifold> {
+    sphere_sphere(closest_point_segment(center, p, q), rc, center, radius)
+}
+
+pub fn capsule_capsule(
+    p1: Vec3,
+    q1: Vec3,
+    r1: f32,
+    p2: Vec3,
+    q2: Vec3,
+    r2: f32,
+) -> Option<Manifold> {
+    let d1 = q1 - p1;
+    let d2 = q2 - p2;
+    let (c1, c2) = closest_points_segments(p1, q1, p2, q2);
+    let mut manifold = sphere_sphere(c1, r1, c2, r2)?;
+
+    // Nearly parallel segments touch along a line: keep both ends of the
+    // overlapping part so the capsules don't see-saw around a single point.
+    if d1.cross(d2).magnitude2() > 1e-4 * d1.magnitude2() * d2.magnitude2() {
+        return Some(manifold);
+    }
+    let len2 = d1.magnitude2();
+    if len2 < f32::EPSILON {
+        return Some(manifold);
+    }
+    let t0 = ((p2 - p1).dot(d1) / len2).clamp(0.0, 1.0);
+    let t1 = ((q2 - p1).dot(d1) / len2).clamp(0.0, 1.0);
+    if (t1 - t0).abs() * len2.sqrt() < f32::EPSILON {
+        return Some(manifold);
+    }
+
+    let normal = manifold.normal;
+    manifold.contacts.clear();
+    manifold.depths.clear();
+    manifold.features.clear();
+    for (k, t) in [t0, t1].into_iter().enumerate() {
+        let a = p1 + d1 * t;
+        let b = closest_point_segment(a, p2, q2);
+        let (pa, pb) = (a + normal * r1, b - normal * r2);
+        manifold.contacts.push((pa, pb));
+        manifold.depths.push((pa - pb).dot(normal));
+        manifold.features.push(k as ContactFeature + 1);
+    }
+
+    Some(manifold)
+}
+
+// Capsule (segment pq) as a, polyhedron (world-space vertices) as b
+pub fn capsule_polyhedra(
+    p: Vec3,
+    q: Vec3,
+    radius: f32,
+    vertices: &[Vec3],
+    faces: &[Vec<usize>],
+) -> Option<Manifold> {
+    // Signed distance to a convex polyhedron is convex, so the deepest point
+    // of the segment can be found with a ternary search.
+    let dist = |t: f32| polyhedra_closest_point(vertices, faces, p + (q - p) * t).2;
+    let (mut lo, mut hi) = (0.0, 1.0);
+    for _ in 0..32 {
+        let m1 = lo + (hi - lo) / 3.0;
+        let m2 = hi - (hi - lo) / 3.0;
+        if dist(m1) < dist(m2) {
+            hi = m2;
+        } else {
+            lo = m1;
+        }
+    }
+    let deepest = sphere_polyhedra(p + (q - p) * (lo + hi) * 0.5, radius, vertices, faces)?;
+
+    // Lying on a face: support the capsule at both ends
+    if let (Some(mp), Some(mq)) = (
+        sphere_polyhedra(p, radius, vertices, faces),
+        sphere_polyhedra(q, radius, vertices, faces),
+    ) {
+        if mp.normal.dot(mq.normal) > 0.999 {
+            return Some(Manifold {
+                normal: mp.normal,
+                contacts: vec![mp.contacts[0], mq.contacts[0]],
+                depths: vec![mp.depths[0], mq.depths[0]],
+                features: vec![1, 2],
+            });
+        }
+    }
+
+    Some(deepest)
+}
+
+// Unique edges of a polyhedron as vertex index pairs, with the outward
+// normals of the two faces meeting at each
+fn polyhedra_edges(vertices: &[Vec3], faces: &[Vec<usize>]) -> Vec<((usize, usize), Vec3, Vec3)> {
+    let normals: Vec<Vec3> = faces
+        .iter()
+        .map(|face| -ccw_normal(face, vertices).normalize())
+        .collect();
+    let mut owner = HashMap::new();
+    for (i, face) in faces.iter().enumerate() {
+        for (&p, &q) in face.iter().circular_tuple_windows() {
+            owner.insert((p, q), i);
+        }
+    }
+
+    let mut edges = Vec::new();
+    for (i, face) in faces.iter().enumerate() {
+        for (&p, &q) in face.iter().circular_tuple_windows() {
+            if p < q {
+                if let Some(&j) = owner.get(&(q, p)) {
+                    edges.push(((p, q), normals[i], normals[j]));
+                }
+            }
+        }
+    }
+    edges
+}
+
+// Whether the arcs a-b and c-d cross on the Gauss map, in which case the
+// edges they stand for make a face of the Minkowski difference
+fn minkowski_face(a: Vec3, b: Vec3, c: Vec3, d: Vec3) -> bool {
+    let b_x_a = b.cross(a);
+    let d_x_c = d.cross(c);
+    let cba = c.dot(b_x_a);
+    let dba = d.dot(b_x_a);
+    let adc = a.dot(d_x_c);
+    let bdc = b.dot(d_x_c);
+    cba * dba < 0.0 && adc * bdc < 0.0 && cba * bdc > 0.0
+}
+
+fn projection(vertices: &[Vec3], axis: Vec3) -> (f32, f32) {
+    vertices
+        .iter()
+        .map(|v| v.dot(axis))
+        .fold((f32::INFINITY, f32::NEG_INFINITY), |(min, max), d| {
+            (min.min(d), max.max(d))
+        })
+}
+
+// Face of `vertices` whose outward normal separates `others` the most, as
+// (separation, face index). The separation is negative while they overlap.
+fn face_query(vertices: &[Vec3], faces: &[Vec<usize>], others: &[Vec3]) -> (f32, usize) {
+    faces
+        .iter()
+        .enumerate()
+        .map(|(i, face)| {
+            let n = -ccw_normal(face, vertices).normalize();
+            (projection(others, n).0 - n.dot(vertices[face[0]]), i)
+        })
+        .fold((f32::NEG_INFINITY, 0), |best, query| {
+            if query.0 > best.0 {
+                query
+            } else {
+                best
+            }
+        })
+}
+
+struct EdgeQuery {
+    separation: f32,
+    // Unit, from a to b
+    normal: Vec3,
+    // Vertex indices of the edges
+    edge_a: (usize, usize),
+    edge_b: (usize, usize),
+}
+
+// Cross products of an edge of a and an edge of b. Only pairs of edges whose
+// arcs cross on the Gauss map count, those are the ones that can actually
+// touch each other. Along such an axis the edges are the supporting features
+// of both polyhedra, so the separation is just the distance between them.
+fn edge_query(
+    a_vertices: &[Vec3],
+    a_faces: &[Vec<usize>],
+    b_vertices: &[Vec3],
+    b_faces: &[Vec<usize>],
+) -> Option<EdgeQuery> {
+    let a_center = a_vertices.iter().sum::<Vec3>() / a_vertices.len() as f32;
+    let b_edges = polyhedra_edges(b_vertices, b_faces);
+    let mut best: Option<EdgeQuery> = None;
+
+    for (edge_a, u_a, v_a) in polyhedra_edges(a_vertices, a_faces) {
+        let (pa, qa) = (a_vertices[edge_a.0], a_vertices[edge_a.1]);
+        for &(edge_b, u_b, v_b) in &b_edges {
+            // b's normals are flipped in the Minkowski difference a - b
+            if !minkowski_face(u_a, v_a, -u_b, -v_b) {
+                continue;
+            }
+
+            let (pb, qb) = (b_vertices[edge_b.0], b_vertices[edge_b.1]);
+            let axis = (qa - pa).cross(qb - pb);
+            if axis.magnitude2() < 1e-6 * (qa - pa).magnitude2() * (qb - pb).magnitude2() {
+                continue;
+            }
+            let mut normal = axis.normalize();
+            if normal.dot(pa - a_center) < 0.0 {
+                normal = -normal;
+            }
+
+            let separation = normal.dot(pb - pa);
+            if best.as_ref().is_none_or(|e| separation > e.separation) {
+                best = Some(EdgeQuery {
+                    separation,
+                    normal,
+                    edge_a,
+                    edge_b,
+                });
+            }
+        }
+    }
+
+    best
+}
+
+// Single contact between the EPA witness points, for when clipping the faces
+// finds nothing
+fn epa_contact(a: &dyn Collider, b: &dyn Collider) -> Option<Manifold> {
+    let simplex = gjk(a, b, true)?;
+    let (normal, _, pa, pb) = epa(a, b, simplex);
+    if normal.magnitude2().is_nan() {
+        return None;
+    }
+
+    Some(Manifold {
+        normal,
+        contacts: vec![(pa, pb)],
+        depths: vec![(pa - pb).dot(normal).max(0.0)],
+        features: vec![contact_feature(&[2])],
+    })
+}
+
+// Separating axis test over the face normals of both polyhedra and the cross
+// products of their edges (Gregorius, "The Separating Axis Test between
+// Convex Polyhedra"). A face axis gives a face contact, clipped against the
+// reference face, an edge axis a single contact between the closest points of
+// the two edges. Faces are preferred unless an edge axis is clearly shallower.
+pub fn polyhedra_polyhedra(a: &dyn Collider, b: &dyn Collider) -> Option<Manifold> {
+    let a_vertices = a.get_vertices()?;
+    let a_faces = a.get_faces()?;
+    let b_vertices = b.get_vertices()?;
+    let b_faces = b.get_faces()?;
+    let tolerance = 0.005;
+
+    let (a_separation, a_face) = face_query(&a_vertices, a_faces, &b_vertices);
+    if a_separation > 0.0 {
+        return None;
+    }
+    let (b_separation, b_face) = face_query(&b_vertices, b_faces, &a_vertices);
+    if b_separation > 0.0 {
+        return None;
+    }
+    let edge = edge_query(&a_vertices, a_faces, &b_vertices, b_faces);
+    if edge.as_ref().is_some_and(|e| e.separation > 0.0) {
+        return None;
+    }
+
+    let face_separation = a_separation.max(b_separation);
+    if let Some(edge) = edge.filter(|e| e.separation > face_separation + tolerance) {
+        let (pa, pb) = closest_points_segments(
+            a_vertices[edge.edge_a.0],
+            a_vertices[edge.edge_a.1],
+            b_vertices[edge.edge_b.0],
+            b_vertices[edge.edge_b.1],
+        );
+        let (ea, eb) = (edge.edge_a, edge.edge_b);
+        return Some(Manifold {
+            normal: edge.normal,
+            contacts: vec![(pa, pb)],
+            depths: vec![(pa - pb).dot(edge.normal)],
+            features: vec![contact_feature(&[1, ea.0, ea.1, eb.0, eb.1])],
+        });
+    }
+
+    // Reference face on a unless b's is clearly shallower
+    let a_is_ref = b_separation <= a_separation + tolerance;
+    let (ref_vertices, ref_faces, ref_index, inc_vertices, inc_faces) = if a_is_ref {
+        (&a_vertices, a_faces, a_face, &b_vertices, b_faces)
+    } else {
+        (&b_vertices, b_faces, b_face, &a_vertices, a_faces)
+    };
+    let ref_indices = &ref_faces[ref_index];
+    let ref_normal = -ccw_normal(ref_indices, ref_vertices).normalize();
+    let normal = if a_is_ref { ref_normal } else { -ref_normal };
+
+    // Incident face: the most anti-parallel one on the other polyhedron
+    let inc_index = (0..inc_faces.len()).min_by(|f, g| {
+        let df = -ccw_normal(&inc_faces[*f], inc_vertices).normalize().dot(ref_normal);
+        let dg = -ccw_normal(&inc_faces[*g], inc_vertices).normalize().dot(ref_normal);
+        df.total_cmp(&dg)
+    })?;
+    let inc_indices = &inc_faces[inc_index];
+
+    let mut adj_face_normals = Vec::new();
+    for (edge_a, edge_b) in ref_indices.iter().circular_tuple_windows() {
+        for face in ref_faces {
+            if face == ref_indices {
+                continue;
+            }
+            if face.contains(edge_a) && face.contains(edge_b) {
+                adj_face_normals.push((ref_vertices[*edge_a], ccw_normal(face, ref_vertices)))
+            }
+        }
+    }
+
+    let ref_face: Vec<Vec3> = ref_indices.iter().map(|i| ref_vertices[*i]).collect();
+    let mut result: Vec<Vec3> = inc_indices.iter().map(|i| inc_vertices[*i]).collect();
+    for (origin, clip_normal) in &adj_face_normals {
+        result = clip(&result, origin, clip_normal, true);
+    }
+    result = clip(&result, &ref_face[0], &(-ref_normal), false);
+
+    // A clipped point is either an incident vertex or where an incident edge
+    // crosses one of the side planes
+    let point_feature = |p: Vec3| {
+        let n = inc_indices.len();
+        if let Some(k) = (0..n).find(|k| inc_vertices[inc_indices[*k]] == p) {
+            return contact_feature(&[0, inc_indices[k]]);
+        }
+        let distance = |k: usize| {
+            let (u, v) = (inc_indices[k], inc_indices[(k + 1) % n]);
+            closest_point_segment(p, inc_vertices[u], inc_vertices[v]).distance2(p)
+        };
+        let edge = (0..n).min_by(|i, j| distance(*i).total_cmp(&distance(*j))).unwrap();
+        let plane = |k: usize| {
+            let (origin, normal) = adj_face_normals[k];
+            (p - origin).dot(normal.normalize()).abs()
+        };
+        let side = (0..adj_face_normals.len())
+            .min_by(|i, j| plane(*i).total_cmp(&plane(*j)))
+            .unwrap_or(0);
+        contact_feature(&[1, inc_indices[edge], inc_indices[(edge + 1) % n], side])
+    };
+
+    let mut contact_list = Vec::new();
+    let mut depth_list = Vec::new();
+    let mut feature_list = Vec::new();
+    for contact_inc in result {
+        feature_list.push(contact_feature(&[
+            a_is_ref as usize,
+            ref_index,
+            inc_index,
+            point_feature(contact_inc) as usize,
+        ]));
+        // Depth below the reference face
+        let depth = (ref_face[0] - contact_inc).dot(ref_normal);
+        let projected = contact_inc + depth * ref_normal;
+
+        if a_is_ref {
+            contact_list.push((projected, contact_inc));
+        } else {
+            contact_list.push((contact_inc, projected));
+        }
+        depth_list.push(depth);
+    }
+    if contact_list.is_empty() {
+        return epa_contact(a, b);
+    }
+
+    Some(Manifold {
+        normal,
+        contacts: contact_list,
+        depths: depth_list,
+        features: feature_list,
+    })
+}
+
+#[cfg(test)]
+mod contact_test {
+    use super::*;
+    use crate::body::*;
+    use crate::collision::chull::*;
+    use crate::collision::heightfield::*;
+    use crate::cube::*;
+    use crate::inertiatensor::*;
+    use crate::particle::*;
+    use std::cell::RefCell;
+    use std::rc::Rc;
+
+    #[test]
+    fn sphere_contacts() {
+        let s1 =
+            Rc::new(RefCell::new(Particle::new(vec3(0.0, 0.9, 0.0), 1.0))) as Rc<RefCell<dyn Body>>;
+        let s2 =
+            Rc::new(RefCell::new(Particle::new(vec3(0.3, 0.9, 0.0), 1.0))) as Rc<RefCell<dyn Body>>;
+        let cube =
+            Rc::new(RefCell::new(Particle::new(vec3(0.0, 0.0, 0.0), 1.0))) as Rc<RefCell<dyn Body>>;
+
+        let c1 = SphereCollider::new(&s1, 0.5);
+        let c2 = SphereCollider::new(&s2, 0.5);
+        let c3 = PolyhedraCollider::new(&cube, cube_polyhedra(1.0));
+
+        let m = generate_contacts(&c1, &c2).unwrap();
+        assert!((m.normal - Vec3::unit_x()).magnitude() < 1e-5);
+        assert!((m.depths[0] - 0.7).abs() < 1e-5);
+
+        let m = generate_contacts(&c1, &c3).unwrap();
+        assert!((m.normal + Vec3::unit_y()).magnitude() < 1e-5);
+        assert!((m.depths[0] - 0.1).abs() < 1e-5);
+        let (pa, pb) = m.contacts[0];
+        assert!(((pa - pb).dot(m.normal) - m.depths[0]).abs() < 1e-5);
+
+        let m = generate_contacts(&c3, &c2).unwrap();
+        assert!((m.normal - Vec3::unit_y()).magnitude() < 1e-5);
+
+        // Center inside the cube
+        s1.as_ref().borrow_mut().update_pos(vec3(0.1, -0.6, 0.0));
+        let m = generate_contacts(&c1, &c3).unwrap();
+        assert!((m.normal + Vec3::unit_y()).magnitude() < 1e-5);
+        assert!((m.depths[0] - 0.7).abs() < 1e-5);
+
+        s1.as_ref().borrow_mut().update_pos(vec3(-0.1, 0.8, 0.0));
+        assert!(generate_contacts(&c1, &c3).is_none());
+    }
+
+    #[test]
+    fn capsule_contacts() {
+        let lying = Rc::new(RefCell::new(RigidBody::new(
+            vec3(0.0, 0.7, 0.0),
+            Quat::from_angle_z(Deg(90.0)),
+            1.0,
+            zeroinertia_mass(),
+        ))) as Rc<RefCell<dyn Body>>;
+        let cube =
+            Rc::new(RefCell::new(Particle::new(vec3(0.0, 0.0, 0.0), 1.0))) as Rc<RefCell<dyn Body>>;
+
+        let c1 = CapsuleCollider::new(&lying, 0.4, 0.25);
+        let c2 = PolyhedraCollider::new(&cube, cube_polyhedra(1.0));
+
+        let m = generate_contacts(&c1, &c2).unwrap();
+        assert_eq!(m.contacts.len(), 2);
+        assert!((m.normal + Vec3::unit_y()).magnitude() < 1e-4);
+        for depth in &m.depths {
+            assert!((depth - 0.05).abs() < 1e-4);
+        }
+
+        let m = generate_contacts(&c2, &c1).unwrap();
+        assert!((m.normal - Vec3::unit_y()).magnitude() < 1e-4);
+    }
+
+    #[test]
+    fn offset_contacts() {
+        // L-shape: two boxes on one body, turned a quarter around y
+        let body = Rc::new(RefCell::new(RigidBody::new(
+            vec3(0.0, 0.4, 0.0),
+            Quat::from_angle_y(Deg(90.0)),
+            1.0,
+            zeroinertia_mass(),
+        ))) as Rc<RefCell<dyn Body>>;
+        let ground = PlaneCollider::new(Vec3::unit_y(), 0.0);
+        let foot = PolyhedraCollider::new(&body, cube_polyhedra(1.0))
+            .with_offset(vec3(1.0, 0.0, 0.0), Quat::one());
+        let ball = SphereCollider::new(&body, 0.5).with_offset(vec3(0.0, 1.0, 0.0));
+
+        let m = generate_contacts(&foot, &ground).unwrap();
+        assert_eq!(m.contacts.len(), 4);
+        for (pa, _) in &m.contacts {
+            assert!((pa.z + 1.0).abs() < 0.5 + 1e-4);
+        }
+        assert!(generate_contacts(&ball, &ground).is_none());
+        assert!((ball.get_sphere().unwrap().0 - vec3(0.0, 1.4, 0.0)).magnitude() < 1e-5);
+    }
+
+    #[test]
+    fn plane_contacts() {
+        let cube = Rc::new(RefCell::new(RigidBody::new(
+            vec3(0.0, 0.4, 0.0),
+            Quat::one(),
+            1.0,
+            zeroinertia_mass(),
+        ))) as Rc<RefCell<dyn Body>>;
+        let ball =
+            Rc::new(RefCell::new(Particle::new(vec3(3.0, 0.2, 0.0), 1.0))) as Rc<RefCell<dyn Body>>;
+
+        let ground = PlaneCollider::new(Vec3::unit_y(), 0.0);
+        let c1 = PolyhedraCollider::new(&cube, cube_polyhedra(1.0));
+        let c2 = SphereCollider::new(&ball, 0.25);
+
+        let m = generate_contacts(&c1, &ground).unwrap();
+        assert_eq!(m.contacts.len(), 4);
+        assert!((m.normal + Vec3::unit_y()).magnitude() < 1e-5);
+        for (depth, (pa, pb)) in m.depths.iter().zip(m.contacts.iter()) {
+            assert!((depth - 0.1).abs() < 1e-5);
+            assert!(((pa - pb).dot(m.normal) - depth).abs() < 1e-5);
+        }
+
+        let m = generate_contacts(&ground, &c2).unwrap();
+        assert!((m.depths[0] - 0.05).abs() < 1e-5);
+
+        cube.as_ref().borrow_mut().update_pos(vec3(0.0, 0.2, 0.0));
+        assert!(generate_contacts(&ground, &c1).is_none());
+    }
+
+    #[test]
+    fn trimesh_contacts() {
+        let ground = TriMeshCollider::from_cpu_mesh(
+            &CpuMesh::square(),
+            Mat4::from_scale(5.0) * Mat4::from_angle_x(Deg(-90.0)),
+        );
+        let ball =
+            Rc::new(RefCell::new(Particle::new(vec3(1.0, 0.2, 2.0), 1.0))) as Rc<RefCell<dyn Body>>;
+        let cube = Rc::new(RefCell::new(Particle::new(vec3(-3.0, 0.4, 1.0), 1.0)))
+            as Rc<RefCell<dyn Body>>;
+        let c1 = SphereCollider::new(&ball, 0.25);
+        let c2 = PolyhedraCollider::new(&cube, cube_polyhedra(1.0));
+
+        let manifolds = generate_manifolds(&c1, &ground);
+        assert_eq!(manifolds.len(), 1);
+        assert!((manifolds[0].normal + Vec3::unit_y()).magnitude() < 1e-4);
+        assert!((manifolds[0].depths[0] - 0.05).abs() < 1e-4);
+
+        let manifolds = generate_manifolds(&ground, &c2);
+        assert!(!manifolds.is_empty());
+        for m in manifolds {
+            assert!(!m.contacts.is_empty());
+            assert!((m.normal - Vec3::unit_y()).magnitude() < 1e-3);
+            for depth in m.depths {
+                assert!((depth - 0.1).abs() < 1e-3);
+            }
+        }
+
+        ball.as_ref().borrow_mut().update_pos(vec3(0.0, 0.1, 0.0));
+        assert!(generate_manifolds(&ground, &c1).is_empty());
+    }
+
+    #[test]
+    fn heightfield_contacts() {
+        // Slope rising by 0.5 per unit along x
+        let heights = (0..25).map(|k| (k % 5) as f32 * 0.5).collect();
+        let terrain =
+            HeightfieldCollider::new(vec3(-2.0, 0.0, -2.0), 5, 5, heights, vec3(1.0, 1.0, 1.0));
+        assert_eq!(terrain.to_cpu_mesh().indices.len(), Some(4 * 4 * 6));
+
+        let ball =
+            Rc::new(RefCell::new(Particle::new(vec3(0.3, 1.2, 0.3), 1.0))) as Rc<RefCell<dyn Body>>;
+        let c1 = SphereCollider::new(&ball, 0.25);
+
+        let manifolds = generate_manifolds(&terrain, &c1);
+        assert!(!manifolds.is_empty());
+        let slope_normal = vec3(-0.5, 1.0, 0.0).normalize();
+        let height = 0.5 * (0.3 + 2.0);
+        let expected = 0.25 - (1.2 - height) * slope_normal.y;
+        for m in manifolds {
+            assert!((m.normal - slope_normal).magnitude() < 1e-3);
+            assert!((m.depths[0] - expected).abs() < 1e-3);
+        }
+    }
+
+    #[test]
+    fn polyhedra_contacts() {
+        let rigid = |pos: Vec3, apos: Quat| {
+            Rc::new(RefCell::new(RigidBody::new(
+                pos,
+                apos,
+                1.0,
+                cubeinertia_mass(1.0),
+            ))) as Rc<RefCell<dyn Body>>
+        };
+        let half = 0.5f32.sqrt();
+
+        // Edge along z on top of a, edge along x at the bottom of b
+        let a = rigid(Vec3::zero(), Quat::from_angle_z(Deg(45.0)));
+        let b = rigid(
+            vec3(0.1, 2.0 * half - 0.05, -0.2),
+            Quat::from_angle_x(Deg(45.0)),
+        );
+        let ca = PolyhedraCollider::new(&a, cube_polyhedra(1.0));
+        let cb = PolyhedraCollider::new(&b, cube_polyhedra(1.0));
+
+        let m = generate_contacts(&ca, &cb).unwrap();
+        assert!((m.normal - Vec3::unit_y()).magnitude() < 1e-4);
+        assert_eq!(m.contacts.len(), 1);
+        assert!((m.depths[0] - 0.05).abs() < 1e-4);
+        let (pa, pb) = m.contacts[0];
+        assert!((pa - vec3(0.0, half, -0.2)).magnitude() < 1e-4);
+        assert!((pb - vec3(0.0, half - 0.05, -0.2)).magnitude() < 1e-4);
+
+        // Seen from b
+        let m = generate_contacts(&cb, &ca).unwrap();
+        assert!((m.normal + Vec3::unit_y()).magnitude() < 1e-4);
+        assert!((m.depths[0] - 0.05).abs() < 1e-4);
+
+        b.as_ref().borrow_mut().update_pos(vec3(0.0, 0.1, 0.0));
+        assert!(generate_contacts(&ca, &cb).is_none());
+
+        // Face on face, turned so that the clipped contacts form an octagon
+        let a = rigid(Vec3::zero(), Quat::one());
+        let b = rigid(vec3(0.0, 0.95, 0.0), Quat::from_angle_y(Deg(45.0)));
+        let ca = PolyhedraCollider::new(&a, cube_polyhedra(1.0));
+        let cb = PolyhedraCollider::new(&b, cube_polyhedra(1.0));
+
+        let m = generate_contacts(&ca, &cb).unwrap();
+        assert!((m.normal - Vec3::unit_y()).magnitude() < 1e-4);
+        assert_eq!(m.contacts.len(), MAX_CONTACTS);
+        for ((pa, pb), depth) in m.contacts.iter().zip(&m.depths) {
+            assert!((depth - 0.05).abs() < 1e-4);
+            assert!(((pa - pb).dot(m.normal) - depth).abs() < 1e-4);
+            assert!(pa.x.abs() <= 0.5 + 1e-4 && pa.z.abs() <= 0.5 + 1e-4);
+        }
+        // Spread over the octagon rather than bunched on one side of it
+        let mut angles: Vec<f32> = m.contacts.iter().map(|(pa, _)| pa.z.atan2(pa.x)).collect();
+        angles.sort_by(f32::total_cmp);
+        for (p, q) in angles.iter().circular_tuple_windows() {
+            let gap = (q - p).rem_euclid(2.0 * std::f32::consts::PI);
+            assert!(gap < 2.0, "{:?}", angles);
+        }
+    }
+
+    #[test]
+    fn manifold_reduction() {
+        // Deeper towards +x, on a 5 by 5 grid in the y = 0 plane
+        let contacts: Vec<(Vec3, Vec3)> = (0..25)
+            .map(|k| {
+                let p = vec3((k % 5) as f32, 0.0, (k / 5) as f32);
+                (p, p)
+            })
+            .collect();
+        let depths = contacts.iter().map(|(p, _)| 0.1 + 0.01 * p.x).collect();
+        let m = Manifold {
+            normal: Vec3::unit_y(),
+            features: (0..25).collect(),
+            contacts,
+            depths,
+        }
+        .reduce()
+        .unwrap();
+
+        assert_eq!(m.contacts.len(), 4);
+        assert!((m.depths.iter().cloned().fold(0.0, f32::max) - 0.14).abs() < 1e-6);
+        let mut corners: Vec<(i32, i32)> = m
+            .contacts
+            .iter()
+            .map(|(p, _)| (p.x as i32, p.z as i32))
+            .collect();
+        corners.sort();
+        assert_eq!(corners, vec![(0, 0), (0, 4), (4, 0), (4, 4)]);
+
+        let empty = Manifold {
+            normal: Vec3::unit_y(),
+            contacts: Vec::new(),
+            depths: Vec::new(),
+            features: Vec::new(),
+        };
+        assert!(empty.reduce().is_none());
+    }
+
+    #[test]
+    fn epa_fallback() {
+        let a =
+            Rc::new(RefCell::new(Particle::new(vec3(0.0, 0.0, 0.0), 1.0))) as Rc<RefCell<dyn Body>>;
+        let b =
+            Rc::new(RefCell::new(Particle::new(vec3(0.2, 0.9, 0.1), 1.0))) as Rc<RefCell<dyn Body>>;
+        let ca = PolyhedraCollider::new(&a, cube_polyhedra(1.0));
+        let cb = PolyhedraCollider::new(&b, cube_polyhedra(1.0));
+
+        let m = epa_contact(&ca, &cb).unwrap();
+        assert!((m.normal - Vec3::unit_y()).magnitude() < 1e-3);
+        assert_eq!(m.contacts.len(), 1);
+        let (pa, pb) = m.contacts[0];
+        assert!((m.depths[0] - 0.1).abs() < 1e-3);
+        assert!((pa.y - 0.5).abs() < 1e-3 && (pb.y - 0.4).abs() < 1e-3);
+
+        b.as_ref().borrow_mut().update_pos(vec3(0.0, 0.2, 0.0));
+        assert!(epa_contact(&ca, &cb).is_none());
+    }
+}
@@ -0,0 +1,145 @@
+use super::collider::*;
+use three_d::*;
+
+// Closest point on triangle abc to p (Ericson, Real-Time Collision Detection 5.1.5)
+pub fn closest_point_triangle(p: Vec3, a: Vec3, b: Vec3, c: Vec3) -> Vec3 {
+    let ab = b - a;
+    let ac = c - a;
+    let ap = p - a;
+    let d1 = ab.dot(ap);
+    let d2 = ac.dot(ap);
+    if d1 <= 0.0 && d2 <= 0.0 {
+        return a;
+    }
+
+    let bp = p - b;
+    let d3 = ab.dot(bp);
+    let d4 = ac.dot(bp);
+    if d3 >= 0.0 && d4 <= d3 {
+        return b;
+    }
+
+    let vc = d1 * d4 - d3 * d2;
+    if vc <= 0.0 && d1 >= 0.0 && d3 <= 0.0 {
+        return a + ab * (d1 / (d1 - d3));
+    }
+
+    let cp = p - c;
+    let d5 = ab.dot(cp);
+    let d6 = ac.dot(cp);
+    if d6 >= 0.0 && d5 <= d6 {
+        return c;
+    }
+
+    let vb = d5 * d2 - d1 * d6;
+    if vb <= 0.0 && d2 >= 0.0 && d6 <= 0.0 {
+        return a + ac * (d2 / (d2 - d6));
+    }
+
+    let va = d3 * d6 - d5 * d4;
+    if va <= 0.0 && (d4 - d3) >= 0.0 && (d5 - d6) >= 0.0 {
+        return b + (c - b) * ((d4 - d3) / ((d4 - d3) + (d5 - d6)));
+    }
+
+    let denom = 1.0 / (va + vb + vc);
+    a + ab * (vb * denom) + ac * (vc * denom)
+}
+
+// Closest point on the surface of a convex polyhedron (world-space vertices,
+// faces wound like cube_polyhedra, so ccw_normal points inwards) to p,
+// together with the outward normal there and the signed distance of p from
+// the surface (negative inside).
+pub fn polyhedra_closest_point(
+    vertices: &[Vec3],
+    faces: &[Vec<usize>],
+    p: Vec3,
+) -> (Vec3, Vec3, f32) {
+    let mut max_dist = f32::NEG_INFINITY;
+    let mut max_normal = Vec3::unit_y();
+    for face in faces {
+        let n = -ccw_normal(face, vertices).normalize();
+        let dist = n.dot(p - vertices[face[0]]);
+        if dist > max_dist {
+            max_dist = dist;
+            max_normal = n;
+        }
+    }
+
+    if max_dist <= 0.0 {
+        // Inside: leave through the nearest face
+        return (p - max_normal * max_dist, max_normal, max_dist);
+    }
+
+    let mut min_dist2 = f32::INFINITY;
+    let mut closest = p;
+    for face in faces {
+        for k in 1..(face.len() - 1) {
+            let q = closest_point_triangle(
+                p,
+                vertices[face[0]],
+                vertices[face[k]],
+                vertices[face[k + 1]],
+            );
+            let dist2 = q.distance2(p);
+            if dist2 < min_dist2 {
+                min_dist2 = dist2;
+                closest = q;
+            }
+        }
+    }
+
+    let dist = min_dist2.sqrt();
+    if dist < f32::EPSILON {
+        return (closest, max_normal, 0.0);
+    }
+    (closest, (p - closest) / dist, dist)
+}
+
+pub fn closest_point_segment(p: Vec3, a: Vec3, b: Vec3) -> Vec3 {
+    let ab = b - a;
+    let len2 = ab.magnitude2();
+    if len2 < f32::EPSILON {
+        return a;
+    }
+    a + ab * ((p - a).dot(ab) / len2).clamp(0.0, 1.0)
+}
+
+// Closest points between segments p1q1 and p2q2 (Ericson 5.1.9)
+pub fn closest_points_segments(p1: Vec3, q1: Vec3, p2: Vec3, q2: Vec3) -> (Vec3, Vec3) {
+    let d1 = q1 - p1;
+    let d2 = q2 - p2;
+    let r = p1 - p2;
+    let a = d1.magnitude2();
+    let e = d2.magnitude2();
+    let f = d2.dot(r);
+
+    if a < f32::EPSILON && e < f32::EPSILON {
+        return (p1, p2);
+    }
+    if a < f32::EPSILON {
+        return (p1, p2 + d2 * (f / e).clamp(0.0, 1.0));
+    }
+
+    let c = d1.dot(r);
+    if e < f32::EPSILON {
+        return (p1 + d1 * (-c / a).clamp(0.0, 1.0), p2);
+    }
+
+    let b = d1.dot(d2);
+    let denom = a * e - b * b;
+    let mut s = if denom > f32::EPSILON {
+        ((b * f - c * e) / denom).clamp(0.0, 1.0)
+    } else {
+        0.0
+    };
+    let mut t = (b * s + f) / e;
+    if t < 0.0 {
+        t = 0.0;
+        s = (-c / a).clamp(0.0, 1.0);
+    } else if t > 1.0 {
+        t = 1.0;
+        s = ((b - c) / a).clamp(0.0, 1.0);
+    }
+
+    (p1 + d1 * s, p2 + d2 * t)
+}
@@ -2,8 +2,10 @@ use std::collections::hash_set::SymmetricDifference;
 use std::f32::consts::FRAC_PI_3;
 
 use super::polytope::{get_ccw_normal, Polytope};
+use super::raycast::*;
 use super::simplex::*;
 use super::{collider::*, polytope};
+use std::iter::zip;
 use three_d::*;
 
 fn triangle_point_proj(t: [&Point3<f32>; 3], point: &Point3<f32>) -> Point3<f32> {
@@ -66,11 +68,11 @@ fn tri_pnt_dist(t: [&Point3<f32>; 3], p: &Point3<f32>) -> f32 {
 }
 
 pub fn gjk(
-    a: &Box<dyn Collider>,
-    b: &Box<dyn Collider>,
+    a: &dyn Collider,
+    b: &dyn Collider,
     return_tetrahedron: bool,
 ) -> Option<Simplex> {
-    let mut simplex = Simplex::Point(support(&a, &b, Vec3::unit_x()), Vec3::unit_x()); // last inserted item must be at the end
+    let mut simplex = Simplex::Point(support(a, b, Vec3::unit_x()), Vec3::unit_x()); // last inserted item must be at the end
 
     loop {
         match simplex {
@@ -82,7 +84,7 @@ pub fn gjk(
                 // get direction to the origin
                 let dir = Point3::origin() - A;
                 // get support point there
-                let D = support(&a, &b, dir);
+                let D = support(a, b, dir);
                 // check whether it crossed the origin. if not, return false
                 if dir.dot(D - Point3::origin()) < 0.0 {
                     return None;
@@ -106,7 +108,7 @@ pub fn gjk(
                 // get normal direction towards the origin: triple product
                 let dir = AB.cross(BO).cross(AB);
                 // get support point there
-                let D = support(&a, &b, dir);
+                let D = support(a, b, dir);
                 // check wherther it crossed the origin. if not, return false
                 if dir.dot(D - Point3::origin()) < 0.0 {
                     return None;
@@ -137,7 +139,7 @@ pub fn gjk(
                     if AC.cross(CO).cross(AC).dot(AC.cross(-AB).cross(AC)) > 0.0 {
                         let dir = AC.cross(CO).cross(AC);
                         assert!(dir.dot(CO) > 0.0);
-                        let D = support(&a, &b, dir);
+                        let D = support(a, b, dir);
                         if dir.dot(D - Point3::origin()) < 0.0 {
                             return None;
                         }
@@ -145,7 +147,7 @@ pub fn gjk(
                     } else if BC.cross(CO).cross(BC).dot(BC.cross(AB).cross(BC)) > 0.0 {
                         let dir = BC.cross(CO).cross(BC);
                         assert!(dir.dot(CO) > 0.0);
-                        let D = support(&a, &b, dir);
+                        let D = support(a, b, dir);
                         if dir.dot(D - Point3::origin()) > 0.0 {
                             return None;
                         }
@@ -165,7 +167,7 @@ pub fn gjk(
                         dir *= -1.0;
                     }
                     let dir = dir;
-                    let D = support(&a, &b, dir);
+                    let D = support(a, b, dir);
                     if dir.dot(D - Point3::origin()) < 0.0 {
                         return None;
                     }
@@ -231,19 +233,19 @@ pub fn gjk(
                 }
 
                 // if overABD && !overBCD && !overACD {
-                //     let E = support(&a, &b, ABDn);
+                //     let E = support(a, b, ABDn);
                 //     if ABDn.dot(E - Point3::origin()) < 0.0 {
                 //         return None;
                 //     }
                 //     simplex = Simplex::Tetrahedron(A, B, D, E, ad, bd, dd, ABDn);
                 // } else if !overABD && overBCD && !overACD {
-                //     let E = support(&a, &b, BCDn);
+                //     let E = support(a, b, BCDn);
                 //     if BCDn.dot(E - Point3::origin()) < 0.0 {
                 //         return None;
                 //     }
                 //     simplex = Simplex::Tetrahedron(B, C, D, E, bd, cd, dd, BCDn);
                 // } else if !overABD && !overBCD && overACD {
-                //     let E = support(&a, &b, ACDn);
+                //     let E = support(a, b, ACDn);
                 //     if ACDn.dot(E - Point3::origin()) < 0.0 {
                 //         return None;
                 //     }
@@ -254,21 +256,21 @@ pub fn gjk(
                 //     return None;
                 // } else if overABD && overBCD && !overACD {
                 //     let DBn = -DB.cross(DO).cross(DB);
-                //     let E = support(&a, &b, DBn);
+                //     let E = support(a, b, DBn);
                 //     if DBn.dot(E - Point3::origin()) < 0.0 {
                 //         return None;
                 //     }
                 //     simplex = Simplex::Tetrahedron(B, C, D, E, bd, cd, dd, DBn);
                 // } else if !overABD && overBCD && overACD {
                 //     let DCn = -DC.cross(DO).cross(DC);
-                //     let E = support(&a, &b, DCn);
+                //     let E = support(a, b, DCn);
                 //     if DCn.dot(E - Point3::origin()) < 0.0 {
                 //         return None;
                 //     }
                 //     simplex = Simplex::Tetrahedron(A, C, D, E, ad, cd, dd, DCn);
                 // } else if overABD && !overBCD && overACD {
                 //     let DAn = -DA.cross(DO).cross(DA);
-                //     let E = support(&a, &b, DAn);
+                //     let E = support(a, b, DAn);
                 //     if DAn.dot(E - Point3::origin()) < 0.0 {
                 //         return None;
                 //     }
@@ -282,7 +284,7 @@ pub fn gjk(
     }
 }
 
-pub fn epa(a: &Box<dyn Collider>, b: &Box<dyn Collider>, s: Simplex) -> (Vec3, f32, Vec3, Vec3) {
+pub fn epa(a: &dyn Collider, b: &dyn Collider, s: Simplex) -> (Vec3, f32, Vec3, Vec3) {
     let mut simplex = s;
     if let Simplex::Point(A, ad) = simplex {
         let mut s = Simplex::Point(A, ad);
@@ -294,7 +296,7 @@ pub fn epa(a: &Box<dyn Collider>, b: &Box<dyn Collider>, s: Simplex) -> (Vec3, f
             -Vec3::unit_y(),
             -Vec3::unit_z(),
         ] {
-            s = Simplex::Line(A, support(&a, &b, dir), ad, dir);
+            s = Simplex::Line(A, support(a, b, dir), ad, dir);
             if !s.is_dup() {
                 break;
             }
@@ -322,7 +324,7 @@ pub fn epa(a: &Box<dyn Collider>, b: &Box<dyn Collider>, s: Simplex) -> (Vec3, f
 
         let mut s = Simplex::Triangle(A, A, B, ad, ad, bd);
         for i in 0..5 {
-            let D = support(&a, &b, d);
+            let D = support(a, b, d);
             s = Simplex::Triangle(A, B, D, ad, bd, d);
 
             if !s.is_dup() {
@@ -334,11 +336,11 @@ pub fn epa(a: &Box<dyn Collider>, b: &Box<dyn Collider>, s: Simplex) -> (Vec3, f
     }
     if let Simplex::Triangle(A, B, C, ad, bd, cd) = simplex {
         let dir = (A - B).cross(B - C);
-        let D = support(&a, &b, dir);
+        let D = support(a, b, dir);
 
         let mut s = Simplex::Tetrahedron(A, B, C, D, ad, bd, cd, dir);
         if s.is_dup() {
-            let D = support(&a, &b, -dir);
+            let D = support(a, b, -dir);
 
             s = Simplex::Tetrahedron(A, B, C, D, ad, bd, cd, dir);
         }
@@ -481,16 +483,216 @@ pub fn epa(a: &Box<dyn Collider>, b: &Box<dyn Collider>, s: Simplex) -> (Vec3, f
 
         let mut n = get_ccw_normal(triangle[0], triangle[1], triangle[2]);
 
-        polytope.expand(support(&a, &b, n), n);
+        polytope.expand(support(a, b, n), n);
     }
 }
 
+pub struct Distance {
+    pub distance: f32,
+    // Closest points on a and b
+    pub point_a: Vec3,
+    pub point_b: Vec3,
+    // Unit separating axis pointing from a to b
+    pub axis: Vec3,
+}
+
+// Barycentric coordinates of the point closest to the origin on the affine
+// hull of `points`, None if the points are degenerate
+fn affine_closest(points: &[Vec3]) -> Option<Vec<f32>> {
+    let p0 = points[0];
+    let e: Vec<Vec3> = points[1..].iter().map(|p| p - p0).collect();
+    let mu: Vec<f32> = match e.len() {
+        0 => vec![],
+        1 => {
+            let len2 = e[0].magnitude2();
+            if len2 < f32::EPSILON * f32::EPSILON {
+                return None;
+            }
+            vec![-p0.dot(e[0]) / len2]
+        }
+        2 => {
+            let m = Mat2::new(
+                e[0].dot(e[0]),
+                e[0].dot(e[1]),
+                e[1].dot(e[0]),
+                e[1].dot(e[1]),
+            );
+            if m.determinant().abs() < f32::EPSILON * m.x.x * m.y.y {
+                return None;
+            }
+            let x = m.invert()? * vec2(-p0.dot(e[0]), -p0.dot(e[1]));
+            vec![x.x, x.y]
+        }
+        _ => {
+            let m = Mat3::from_cols(e[0], e[1], e[2]);
+            if m.determinant().abs() < f32::EPSILON {
+                return None;
+            }
+            let x = m.invert()? * -p0;
+            vec![x.x, x.y, x.z]
+        }
+    };
+
+    let mut lambda = vec![1.0 - mu.iter().sum::<f32>()];
+    lambda.extend(mu);
+    Some(lambda)
+}
+
+// Closest point to the origin on the simplex, as the smallest sub-simplex
+// containing it and its barycentric coordinates there
+fn simplex_closest(vertices: &[SimplexVertex]) -> (Vec<SimplexVertex>, Vec<f32>, Vec3) {
+    let mut best = (vec![vertices[0]], vec![1.0], vertices[0].0.to_vec());
+    let mut best_dist2 = f32::INFINITY;
+
+    for mask in 1..(1usize << vertices.len()) {
+        let subset: Vec<SimplexVertex> = (0..vertices.len())
+            .filter(|i| mask & (1 << i) != 0)
+            .map(|i| vertices[i])
+            .collect();
+        let points: Vec<Vec3> = subset.iter().map(|(p, _)| p.to_vec()).collect();
+        let Some(lambda) = affine_closest(&points) else {
+            continue;
+        };
+        if lambda.iter().any(|l| *l <= 0.0) {
+            continue;
+        }
+
+        let closest: Vec3 = zip(&lambda, &points).map(|(l, p)| *l * p).sum();
+        let dist2 = closest.magnitude2();
+        if dist2 < best_dist2 {
+            best_dist2 = dist2;
+            best = (subset, lambda, closest);
+        }
+    }
+
+    best
+}
+
+// Distance between two separated colliders. Returns None when they overlap,
+// in which case gjk and epa give the penetration instead.
+pub fn gjk_distance(a: &dyn Collider, b: &dyn Collider) -> Option<Distance> {
+    let dir = Vec3::unit_x();
+    let mut simplex = Simplex::Point(support(a, b, dir), dir);
+    let mut lambda = vec![1.0];
+    let mut v = support(a, b, dir).to_vec();
+
+    for _ in 0..64 {
+        let dist2 = v.magnitude2();
+        if dist2 < 1e-12 {
+            return None;
+        }
+
+        let dir = -v;
+        let w = support(a, b, dir);
+        // No support point gets closer to the origin
+        if dist2 - v.dot(w.to_vec()) <= 1e-6 * dist2 {
+            break;
+        }
+
+        let mut vertices = simplex.vertices();
+        if vertices.iter().any(|(p, _)| p.distance2(w) < 1e-12) {
+            break;
+        }
+        vertices.push((w, dir));
+
+        let (reduced, l, closest) = simplex_closest(&vertices);
+        if reduced.len() == 4 {
+            // Origin inside the tetrahedron
+            return None;
+        }
+        if closest.magnitude2() >= dist2 {
+            break;
+        }
+        simplex = Simplex::from_vertices(&reduced);
+        lambda = l;
+        v = closest;
+    }
+
+    let mut point_a = Vec3::zero();
+    let mut point_b = Vec3::zero();
+    for ((_, dir), l) in zip(simplex.vertices(), lambda) {
+        point_a += l * a.support(dir).to_vec();
+        point_b += l * b.support(-dir).to_vec();
+    }
+    let distance = v.magnitude();
+
+    Some(Distance {
+        distance,
+        point_a,
+        point_b,
+        axis: -v / distance,
+    })
+}
+
+// Ray against any convex collider through its support function (van den
+// Bergen, "Ray Casting against General Convex Objects"). The simplex is
+// built from support points p, kept in the direction slot, over x - p.
+pub fn gjk_raycast<T: Collider + ?Sized>(
+    collider: &T,
+    origin: Vec3,
+    dir: Vec3,
+    max_t: f32,
+) -> Option<RayHit> {
+    let mut t = 0.0;
+    let mut x = origin;
+    let mut normal = Vec3::zero();
+
+    let first = collider.support(-dir).to_vec();
+    let mut points: Vec<Vec3> = Vec::new();
+    let mut v = x - first;
+    let scale = v.magnitude2().max(1.0);
+
+    for _ in 0..64 {
+        if v.magnitude2() < 1e-10 * scale {
+            break;
+        }
+
+        let p = collider.support(v).to_vec();
+        let w = x - p;
+        if v.dot(w) > 0.0 {
+            if v.dot(dir) >= 0.0 {
+                return None;
+            }
+            t -= v.dot(w) / v.dot(dir);
+            if t > max_t {
+                return None;
+            }
+            x = origin + dir * t;
+            normal = v;
+        } else if v.dot(v) - v.dot(w) <= 1e-6 * v.dot(v) {
+            // Touching, but x can't move any closer
+            break;
+        }
+
+        if !points.iter().any(|q| q.distance2(p) < 1e-12) {
+            points.push(p);
+        }
+        let vertices: Vec<SimplexVertex> = points
+            .iter()
+            .map(|p| (Point3::origin() + (x - p), *p))
+            .collect();
+        let (reduced, _, closest) = simplex_closest(&vertices);
+        points = reduced.into_iter().map(|(_, p)| p).collect();
+        v = closest;
+        if points.len() == 4 {
+            break;
+        }
+    }
+
+    if t == 0.0 {
+        return Some(RayHit::inside(origin, dir));
+    }
+    Some(RayHit::new(origin, dir, t, normal.normalize()))
+}
+
 #[cfg(test)]
 mod gjk_test {
     use super::*;
     use crate::body::*;
     use crate::collision::chull::*;
     use crate::collision::collider::*;
+    use crate::cube::*;
+    use crate::inertiatensor::*;
     use crate::particle::*;
     use std::cell::RefCell;
     use std::rc::Rc;
@@ -503,14 +705,49 @@ mod gjk_test {
         let b2 =
             Rc::new(RefCell::new(Particle::new(vec3(1.0, 0.0, 0.0), 1.0))) as Rc<RefCell<dyn Body>>;
 
-        let c1: Box<dyn Collider> = Box::new(SphereCollider::new(&b1, 0.25));
-        let c2: Box<dyn Collider> = Box::new(SphereCollider::new(&b2, 0.25));
+        let c1 = SphereCollider::new(&b1, 0.25);
+        let c2 = SphereCollider::new(&b2, 0.25);
 
-        assert!(gjk(&c1, &c2).is_none());
+        assert!(gjk(&c1, &c2, false).is_none());
 
         b1.as_ref().borrow_mut().update_pos(vec3(0.5, 0.0, 0.0));
 
-        assert!(gjk(&c1, &c2).is_some());
+        assert!(gjk(&c1, &c2, false).is_some());
+    }
+
+    #[test]
+    fn distance() {
+        let b1 =
+            Rc::new(RefCell::new(Particle::new(vec3(0.0, 0.0, 0.0), 1.0))) as Rc<RefCell<dyn Body>>;
+        let b2 =
+            Rc::new(RefCell::new(Particle::new(vec3(2.0, 1.0, 0.0), 1.0))) as Rc<RefCell<dyn Body>>;
+        let b3 = Rc::new(RefCell::new(RigidBody::new(
+            vec3(0.0, 3.0, 0.0),
+            Quat::from_angle_z(Deg(45.0)),
+            1.0,
+            zeroinertia_mass(),
+        ))) as Rc<RefCell<dyn Body>>;
+
+        let cube = PolyhedraCollider::new(&b1, cube_polyhedra(1.0));
+        let sphere = SphereCollider::new(&b2, 0.5);
+        let diamond = PolyhedraCollider::new(&b3, cube_polyhedra(1.0));
+
+        // Sphere against the cube's edge at (0.5, 0.5)
+        let d = gjk_distance(&cube, &sphere).unwrap();
+        let expected = vec3(1.5, 0.5, 0.0).magnitude() - 0.5;
+        assert!((d.distance - expected).abs() < 1e-3);
+        assert!((d.point_a - vec3(0.5, 0.5, 0.0)).magnitude() < 1e-3);
+        assert!((d.point_b.distance(vec3(2.0, 1.0, 0.0)) - 0.5).abs() < 1e-3);
+        assert!((d.axis - vec3(1.5, 0.5, 0.0).normalize()).magnitude() < 1e-3);
+
+        // Corner of the turned cube above the face of the other one
+        let d = gjk_distance(&diamond, &cube).unwrap();
+        assert!((d.distance - (2.5 - 0.5f32.sqrt())).abs() < 1e-4);
+        assert!((d.point_a - vec3(0.0, 3.0 - 0.5f32.sqrt(), d.point_a.z)).magnitude() < 1e-4);
+        assert!((d.axis + Vec3::unit_y()).magnitude() < 1e-4);
+
+        b2.as_ref().borrow_mut().update_pos(vec3(-1.2, -0.5, 0.0));
+        assert!(gjk_distance(&cube, &sphere).is_none());
     }
 
     #[test]
@@ -0,0 +1,198 @@
+use super::aabb::*;
+use super::collider::*;
+use super::trimesh::*;
+use crate::body::*;
+use crate::cube::*;
+use crate::inertiatensor::*;
+use crate::material::Material;
+use std::cell::RefCell;
+use std::rc::Rc;
+use std::vec::Vec;
+use three_d::*;
+
+// Static terrain on a regular grid of `columns` x `rows` height samples,
+// row-major with x along a row and z across rows. Sample (i, j) sits at
+// origin + (i*scale.x, heights[j*columns + i]*scale.y, j*scale.z).
+pub struct HeightfieldCollider {
+    body: Rc<RefCell<dyn Body>>,
+    origin: Vec3,
+    columns: usize,
+    rows: usize,
+    heights: Vec<f32>,
+    scale: Vec3,
+    aabb: Aabb,
+    material: Material,
+}
+
+impl HeightfieldCollider {
+    pub fn new(origin: Vec3, columns: usize, rows: usize, heights: Vec<f32>, scale: Vec3) -> Self {
+        assert!(columns >= 2 && rows >= 2);
+        assert_eq!(heights.len(), columns * rows);
+
+        let (min, max) = heights
+            .iter()
+            .fold((f32::INFINITY, f32::NEG_INFINITY), |(min, max), h| {
+                (min.min(*h), max.max(*h))
+            });
+        let aabb = Aabb::from_points(&[
+            origin + vec3(0.0, min * scale.y - TRIANGLE_PRISM_DEPTH, 0.0),
+            origin
+                + vec3(
+                    (columns - 1) as f32 * scale.x,
+                    max * scale.y,
+                    (rows - 1) as f32 * scale.z,
+                ),
+        ]);
+
+        Self {
+            body: Rc::new(RefCell::new(RigidBody::new(
+                Vec3::zero(),
+                Quat::one(),
+                0.0,
+                zeroinertia_mass(),
+            ))),
+            origin,
+            columns,
+            rows,
+            heights,
+            scale,
+            aabb,
+            material: Material::default(),
+        }
+    }
+
+    pub fn with_material(mut self, material: Material) -> Self {
+        self.material = material;
+        self
+    }
+
+    fn vertex(&self, i: usize, j: usize) -> Vec3 {
+        self.origin
+            + vec3(
+                i as f32 * self.scale.x,
+                self.heights[j * self.columns + i] * self.scale.y,
+                j as f32 * self.scale.z,
+            )
+    }
+
+    // Both triangles of cell (i, j), CCW seen from above
+    fn cell_triangles(&self, i: usize, j: usize) -> [[Vec3; 3]; 2] {
+        let v00 = self.vertex(i, j);
+        let v10 = self.vertex(i + 1, j);
+        let v01 = self.vertex(i, j + 1);
+        let v11 = self.vertex(i + 1, j + 1);
+
+        [[v00, v01, v10], [v10, v01, v11]]
+    }
+
+    fn cell_range(
+        &self,
+        min: f32,
+        max: f32,
+        origin: f32,
+        scale: f32,
+        cells: usize,
+    ) -> (usize, usize) {
+        let first = ((min - origin) / scale).floor().max(0.0) as usize;
+        let last = (((max - origin) / scale).floor().max(0.0) as usize).min(cells - 1);
+        (first, last)
+    }
+
+    pub fn to_cpu_mesh(&self) -> CpuMesh {
+        let mut positions = Vec::with_capacity(self.columns * self.rows);
+        for j in 0..self.rows {
+            for i in 0..self.columns {
+                positions.push(self.vertex(i, j));
+            }
+        }
+
+        let mut indices = Vec::new();
+        for j in 0..(self.rows - 1) {
+            for i in 0..(self.columns - 1) {
+                let v00 = (j * self.columns + i) as u32;
+                let v10 = v00 + 1;
+                let v01 = v00 + self.columns as u32;
+                let v11 = v01 + 1;
+                indices.extend([v00, v01, v10, v10, v01, v11]);
+            }
+        }
+
+        let mut mesh = CpuMesh {
+            positions: Positions::F32(positions),
+            indices: Indices::U32(indices),
+            ..Default::default()
+        };
+        mesh.compute_normals();
+        mesh
+    }
+}
+
+impl Collider for HeightfieldCollider {
+    fn support(&self, dir: Vec3) -> Point3<f32> {
+        let mut maximum = f32::MIN;
+        let mut maxdir = Vec3::zero();
+        for j in 0..self.rows {
+            for i in 0..self.columns {
+                let point = self.vertex(i, j);
+                let dot = point.dot(dir);
+                if dot > maximum {
+                    maximum = dot;
+                    maxdir = point;
+                }
+            }
+        }
+
+        Point3::origin() + maxdir
+    }
+    fn to_local(&self, x: Point3<f32>) -> Vec3 {
+        x.to_vec()
+    }
+    fn get_body(&self) -> Rc<RefCell<dyn Body>> {
+        self.body.clone()
+    }
+    fn material(&self) -> Material {
+        self.material
+    }
+    fn is_concave(&self) -> bool {
+        true
+    }
+    fn get_triangles(&self, aabb: &Aabb) -> Option<Vec<[Vec3; 3]>> {
+        let mut result = Vec::new();
+        if !self.aabb.overlaps(aabb) {
+            return Some(result);
+        }
+
+        // Only the cells under the other shape
+        let (i0, i1) = self.cell_range(
+            aabb.min.x,
+            aabb.max.x,
+            self.origin.x,
+            self.scale.x,
+            self.columns - 1,
+        );
+        let (j0, j1) = self.cell_range(
+            aabb.min.z,
+            aabb.max.z,
+            self.origin.z,
+            self.scale.z,
+            self.rows - 1,
+        );
+        for j in j0..=j1 {
+            for i in i0..=i1 {
+                for triangle in self.cell_triangles(i, j) {
+                    let mut bounds = Aabb::from_points(&triangle);
+                    bounds.min.y -= TRIANGLE_PRISM_DEPTH;
+                    if bounds.overlaps(aabb) {
+                        result.push(triangle);
+                    }
+                }
+            }
+        }
+
+        Some(result)
+    }
+
+    fn aabb(&self) -> Aabb {
+        self.aabb
+    }
+}
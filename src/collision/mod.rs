@@ -4,3 +4,13 @@ pub mod gjk;
 pub mod chull;
 pub mod polytope;
 pub mod clipping;
+pub mod aabb;
+pub mod broadphase;
+pub mod bvh;
+pub mod geometry;
+pub mod contact;
+pub mod trimesh;
+pub mod heightfield;
+pub mod raycast;
+pub mod toi;
+pub mod cache;
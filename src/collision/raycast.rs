@@ -0,0 +1,340 @@
+use super::collider::*;
+use three_d::*;
+
+// The ray is origin + dir*t, so t is in multiples of dir. A ray starting
+// inside a solid hits it at t = 0, facing back along the ray.
+#[derive(Debug, Clone, Copy)]
+pub struct RayHit {
+    pub t: f32,
+    pub point: Vec3,
+    pub normal: Vec3,
+}
+
+impl RayHit {
+    pub fn new(origin: Vec3, dir: Vec3, t: f32, normal: Vec3) -> Self {
+        Self {
+            t,
+            point: origin + dir * t,
+            normal,
+        }
+    }
+
+    pub fn inside(origin: Vec3, dir: Vec3) -> Self {
+        Self::new(origin, dir, 0.0, -dir.normalize())
+    }
+}
+
+pub fn ray_sphere(
+    origin: Vec3,
+    dir: Vec3,
+    max_t: f32,
+    center: Vec3,
+    radius: f32,
+) -> Option<RayHit> {
+    let m = origin - center;
+    let c = m.magnitude2() - radius * radius;
+    if c <= 0.0 {
+        return Some(RayHit::inside(origin, dir));
+    }
+
+    let a = dir.magnitude2();
+    let b = m.dot(dir);
+    let discriminant = b * b - a * c;
+    if b >= 0.0 || discriminant < 0.0 {
+        return None;
+    }
+
+    let t = (-b - discriminant.sqrt()) / a;
+    if t > max_t {
+        return None;
+    }
+    let hit = RayHit::new(origin, dir, t, Vec3::zero());
+    Some(RayHit {
+        normal: (hit.point - center).normalize(),
+        ..hit
+    })
+}
+
+// Half-space normal.dot(x) <= offset
+pub fn ray_plane(origin: Vec3, dir: Vec3, max_t: f32, normal: Vec3, offset: f32) -> Option<RayHit> {
+    let dist = normal.dot(origin) - offset;
+    if dist <= 0.0 {
+        return Some(RayHit::inside(origin, dir));
+    }
+
+    let denom = normal.dot(dir);
+    if denom >= 0.0 {
+        return None;
+    }
+    let t = -dist / denom;
+    if t > max_t {
+        return None;
+    }
+    Some(RayHit::new(origin, dir, t, normal))
+}
+
+// Clips the ray against every face plane of a convex polyhedron with
+// world-space vertices and faces wound like cube_polyhedra.
+pub fn ray_polyhedra(
+    origin: Vec3,
+    dir: Vec3,
+    max_t: f32,
+    vertices: &[Vec3],
+    faces: &[Vec<usize>],
+) -> Option<RayHit> {
+    let mut enter = 0.0;
+    let mut exit = max_t;
+    let mut enter_normal = None;
+
+    for face in faces {
+        let n = -ccw_normal(face, vertices).normalize();
+        let dist = n.dot(origin - vertices[face[0]]);
+        let denom = n.dot(dir);
+
+        if denom.abs() < f32::EPSILON {
+            if dist > 0.0 {
+                return None;
+            }
+            continue;
+        }
+
+        let t = -dist / denom;
+        if denom < 0.0 {
+            if t > enter {
+                enter = t;
+                enter_normal = Some(n);
+            }
+        } else if t < exit {
+            exit = t;
+        }
+        if enter > exit {
+            return None;
+        }
+    }
+
+    match enter_normal {
+        Some(normal) => Some(RayHit::new(origin, dir, enter, normal)),
+        None => Some(RayHit::inside(origin, dir)),
+    }
+}
+
+// Two sided, the normal faces the ray (Moller-Trumbore)
+pub fn ray_triangle(origin: Vec3, dir: Vec3, max_t: f32, triangle: &[Vec3; 3]) -> Option<RayHit> {
+    let [a, b, c] = *triangle;
+    let ab = b - a;
+    let ac = c - a;
+    let p = dir.cross(ac);
+    let det = ab.dot(p);
+    if det.abs() < f32::EPSILON * ab.magnitude() * ac.magnitude() * dir.magnitude() {
+        return None;
+    }
+
+    let inv = 1.0 / det;
+    let s = origin - a;
+    let u = s.dot(p) * inv;
+    if !(0.0..=1.0).contains(&u) {
+        return None;
+    }
+    let q = s.cross(ab);
+    let v = dir.dot(q) * inv;
+    if v < 0.0 || u + v > 1.0 {
+        return None;
+    }
+
+    let t = ac.dot(q) * inv;
+    if t < 0.0 || t > max_t {
+        return None;
+    }
+    let mut normal = ab.cross(ac).normalize();
+    if normal.dot(dir) > 0.0 {
+        normal = -normal;
+    }
+    Some(RayHit::new(origin, dir, t, normal))
+}
+
+// Nearest hit among a set of triangles
+pub fn ray_triangles(
+    origin: Vec3,
+    dir: Vec3,
+    max_t: f32,
+    triangles: &[[Vec3; 3]],
+) -> Option<RayHit> {
+    triangles
+        .iter()
+        .filter_map(|triangle| ray_triangle(origin, dir, max_t, triangle))
+        .min_by(|a, b| a.t.total_cmp(&b.t))
+}
+
+#[cfg(test)]
+mod raycast_test {
+    use super::*;
+    use crate::body::*;
+    use crate::collision::chull::*;
+    use crate::collision::gjk::gjk_raycast;
+    use crate::collision::heightfield::*;
+    use crate::collision::trimesh::*;
+    use crate::cube::*;
+    use crate::inertiatensor::*;
+    use crate::particle::*;
+    use crate::physics::*;
+    use std::cell::RefCell;
+    use std::rc::Rc;
+
+    fn close(hit: Option<RayHit>, t: f32, normal: Vec3) -> bool {
+        let hit = hit.unwrap();
+        (hit.t - t).abs() < 1e-3 && (hit.normal - normal).magnitude() < 1e-3
+    }
+
+    #[test]
+    fn convex_rays() {
+        let ball =
+            Rc::new(RefCell::new(Particle::new(vec3(0.0, 0.0, 0.0), 1.0))) as Rc<RefCell<dyn Body>>;
+        let box_body = Rc::new(RefCell::new(RigidBody::new(
+            vec3(3.0, 0.0, 0.0),
+            Quat::from_angle_y(Deg(45.0)),
+            1.0,
+            zeroinertia_mass(),
+        ))) as Rc<RefCell<dyn Body>>;
+
+        let sphere = SphereCollider::new(&ball, 0.5);
+        let capsule = CapsuleCollider::new(&ball, 1.0, 0.5);
+        let cube = PolyhedraCollider::new(&box_body, cube_polyhedra(1.0));
+        let ground = PlaneCollider::new(Vec3::unit_y(), -1.0);
+
+        let origin = vec3(-3.0, 0.0, 0.0);
+        assert!(close(
+            sphere.cast_ray(origin, Vec3::unit_x(), 10.0),
+            2.5,
+            -Vec3::unit_x()
+        ));
+        assert!(sphere.cast_ray(origin, Vec3::unit_x(), 2.0).is_none());
+        assert!(sphere.cast_ray(origin, Vec3::unit_y(), 10.0).is_none());
+
+        // Down onto the top of the capsule, through GJK
+        let down = -Vec3::unit_y();
+        assert!(close(
+            capsule.cast_ray(vec3(0.0, 4.0, 0.0), down, 10.0),
+            2.5,
+            Vec3::unit_y()
+        ));
+        assert!(close(
+            capsule.cast_ray(origin, Vec3::unit_x(), 10.0),
+            2.5,
+            -Vec3::unit_x()
+        ));
+
+        // Onto the edge of the turned cube
+        let edge = 3.0 - 0.5f32.sqrt();
+        let normal = -vec3(1.0, 0.0, 1.0).normalize();
+        let hit = cube
+            .cast_ray(vec3(0.0, 0.2, 0.0), Vec3::unit_x(), 10.0)
+            .unwrap();
+        assert!((hit.t - edge).abs() < 1e-4);
+        assert!((hit.point - vec3(edge, 0.2, 0.0)).magnitude() < 1e-4);
+        assert!(hit.normal.dot(normal) > 0.7);
+        let gjk = gjk_raycast(&cube, vec3(0.0, 0.2, 0.0), Vec3::unit_x(), 10.0).unwrap();
+        assert!((gjk.t - edge).abs() < 1e-3);
+
+        let ray = vec3(1.0, 0.0, 1.0);
+        let face = 1.0 - 0.5 / 2.0f32.sqrt();
+        assert!(close(
+            cube.cast_ray(vec3(2.0, 0.3, -1.0), ray, 10.0),
+            face,
+            normal
+        ));
+        assert!(close(
+            gjk_raycast(&cube, vec3(2.0, 0.3, -1.0), ray, 10.0),
+            face,
+            normal
+        ));
+        assert!(cube
+            .cast_ray(vec3(0.0, 2.0, 0.0), Vec3::unit_x(), 10.0)
+            .is_none());
+        assert!(gjk_raycast(&cube, vec3(0.0, 2.0, 0.0), Vec3::unit_x(), 10.0).is_none());
+
+        assert!(close(
+            ground.cast_ray(vec3(5.0, 3.0, 5.0), down * 2.0, 10.0),
+            2.0,
+            Vec3::unit_y()
+        ));
+        assert!(ground
+            .cast_ray(vec3(5.0, 3.0, 5.0), Vec3::unit_x(), 10.0)
+            .is_none());
+
+        // Starting inside
+        for hit in [
+            sphere.cast_ray(Vec3::zero(), Vec3::unit_x(), 10.0),
+            cube.cast_ray(vec3(3.0, 0.0, 0.0), Vec3::unit_x(), 10.0),
+            gjk_raycast(&cube, vec3(3.0, 0.0, 0.0), Vec3::unit_x(), 10.0),
+        ] {
+            assert!(close(hit, 0.0, -Vec3::unit_x()));
+        }
+    }
+
+    #[test]
+    fn concave_rays() {
+        let mesh = TriMeshCollider::from_cpu_mesh(
+            &CpuMesh::square(),
+            Mat4::from_scale(5.0) * Mat4::from_angle_x(Deg(-90.0)),
+        );
+        let heights = (0..25).map(|k| (k % 5) as f32 * 0.5).collect();
+        let terrain =
+            HeightfieldCollider::new(vec3(-2.0, 0.0, -2.0), 5, 5, heights, vec3(1.0, 1.0, 1.0));
+
+        let down = -Vec3::unit_y();
+        assert!(close(
+            mesh.cast_ray(vec3(1.0, 2.0, 1.0), down, 10.0),
+            2.0,
+            Vec3::unit_y()
+        ));
+        assert!(close(
+            mesh.cast_ray(vec3(1.0, -2.0, 1.0), -down, 10.0),
+            2.0,
+            down
+        ));
+        assert!(mesh.cast_ray(vec3(6.0, 2.0, 1.0), down, 10.0).is_none());
+
+        // Slope rising by 0.5 per unit along x, height 1 at x = 0
+        let normal = vec3(-0.5, 1.0, 0.0).normalize();
+        assert!(close(
+            terrain.cast_ray(vec3(0.0, 3.0, 0.5), down, 10.0),
+            2.0,
+            normal
+        ));
+        assert!(close(
+            terrain.cast_ray(vec3(-4.0, 0.0, 0.5), Vec3::unit_x(), 10.0),
+            2.0,
+            normal
+        ));
+    }
+
+    #[test]
+    fn physics_ray() {
+        let mut physics = Physics::new(vec3(0.0, -9.8, 0.0), 1, 1);
+        physics.add_collider(PlaneCollider::new(Vec3::unit_y(), 0.0));
+        let near = physics.add_body(Particle::new(vec3(0.0, 2.0, 0.0), 1.0));
+        let far = physics.add_body(Particle::new(vec3(0.0, 4.0, 0.0), 1.0));
+        physics.add_collider(SphereCollider::new(
+            &(near.clone() as Rc<RefCell<dyn Body>>),
+            0.5,
+        ));
+        physics.add_collider(SphereCollider::new(
+            &(far.clone() as Rc<RefCell<dyn Body>>),
+            0.5,
+        ));
+
+        let (body, hit) = physics
+            .cast_ray(vec3(0.0, 10.0, 0.0), -Vec3::unit_y(), 20.0)
+            .unwrap();
+        assert!(Rc::ptr_eq(&body, &(far.clone() as Rc<RefCell<dyn Body>>)));
+        assert!((hit.t - 5.5).abs() < 1e-4);
+
+        let (_, hit) = physics
+            .cast_ray(vec3(3.0, 10.0, 0.0), -Vec3::unit_y(), 20.0)
+            .unwrap();
+        assert!((hit.t - 10.0).abs() < 1e-4);
+        assert!(physics
+            .cast_ray(vec3(3.0, 10.0, 0.0), Vec3::unit_y(), 20.0)
+            .is_none());
+    }
+}
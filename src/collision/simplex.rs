@@ -8,6 +8,9 @@ pub enum Simplex {
     Tetrahedron(Point3<f32>, Point3<f32>, Point3<f32>, Point3<f32>, Vec3, Vec3, Vec3, Vec3)
 }
 
+// Minkowski point with the direction it was found in
+pub type SimplexVertex = (Point3<f32>, Vec3);
+
 impl Simplex {
     pub fn is_dup(&self) -> bool {
         match self {
@@ -23,4 +26,26 @@ impl Simplex {
             }
         }
     }
+
+    pub fn vertices(&self) -> Vec<SimplexVertex> {
+        match *self {
+            Self::Point(a, ad) => vec![(a, ad)],
+            Self::Line(a, b, ad, bd) => vec![(a, ad), (b, bd)],
+            Self::Triangle(a, b, c, ad, bd, cd) => vec![(a, ad), (b, bd), (c, cd)],
+            Self::Tetrahedron(a, b, c, d, ad, bd, cd, dd) => {
+                vec![(a, ad), (b, bd), (c, cd), (d, dd)]
+            }
+        }
+    }
+
+    pub fn from_vertices(v: &[SimplexVertex]) -> Self {
+        match v.len() {
+            1 => Self::Point(v[0].0, v[0].1),
+            2 => Self::Line(v[0].0, v[1].0, v[0].1, v[1].1),
+            3 => Self::Triangle(v[0].0, v[1].0, v[2].0, v[0].1, v[1].1, v[2].1),
+            _ => Self::Tetrahedron(
+                v[0].0, v[1].0, v[2].0, v[3].0, v[0].1, v[1].1, v[2].1, v[3].1,
+            ),
+        }
+    }
 }
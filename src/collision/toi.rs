@@ -0,0 +1,510 @@
+use super::aabb::*;
+use super::collider::*;
+use super::gjk::*;
+use super::trimesh::*;
+use crate::body::*;
+use crate::material::Material;
+use std::cell::RefCell;
+use std::rc::Rc;
+use three_d::*;
+
+// Distance at which two sweeping colliders count as touching
+pub const TOI_TOLERANCE: f32 = 1.0e-3;
+
+// Motion of a collider over t in [0, 1]: its body moves by `translation` and
+// turns by `rotation` (axis times angle in radians) about its position.
+#[derive(Debug, Clone, Copy)]
+pub struct Sweep {
+    pub translation: Vec3,
+    pub rotation: Vec3,
+}
+
+impl Sweep {
+    pub fn new(translation: Vec3, rotation: Vec3) -> Self {
+        Self {
+            translation,
+            rotation,
+        }
+    }
+
+    pub fn translation(translation: Vec3) -> Self {
+        Self::new(translation, Vec3::zero())
+    }
+
+    pub fn zero() -> Self {
+        Self::new(Vec3::zero(), Vec3::zero())
+    }
+
+    // Motion of the body over `dt` at its current velocities
+    pub fn from_body(body: &Rc<RefCell<dyn Body>>, dt: f32) -> Self {
+        let body = body.as_ref().borrow();
+        Self::new(body.vel() * dt, body.avel() * dt)
+    }
+
+    // Rigid motion taking the pose (pos, apos) to (pos_next, apos_next)
+    pub fn between(pos: Vec3, apos: Quat, pos_next: Vec3, apos_next: Quat) -> Self {
+        let mut dq = apos_next * apos.invert();
+        if dq.s < 0.0 {
+            dq = -dq;
+        }
+        let sin = dq.v.magnitude();
+        let rotation = if sin > f32::EPSILON {
+            dq.v / sin * 2.0 * sin.atan2(dq.s)
+        } else {
+            Vec3::zero()
+        };
+
+        Self::new(pos_next - pos, rotation)
+    }
+
+    pub fn rotation_at(&self, t: f32) -> Quat {
+        let angle = self.rotation.magnitude() * t;
+        if angle < f32::EPSILON {
+            return Quat::one();
+        }
+        Quat::from_axis_angle(self.rotation.normalize(), Rad(angle))
+    }
+
+    // The collider as it is at time t
+    pub fn apply<'a>(&self, collider: &'a dyn Collider, t: f32) -> Transformed<'a> {
+        Transformed {
+            collider,
+            pivot: collider.get_body().as_ref().borrow().pos(),
+            translation: self.translation * t,
+            rotation: self.rotation_at(t),
+        }
+    }
+
+    // Furthest any point of the collider gets from the straight line its
+    // pivot moves along
+    fn reach(&self, collider: &dyn Collider) -> f32 {
+        let (center, radius) = collider.bounding_sphere();
+        let pivot = collider.get_body().as_ref().borrow().pos();
+        center.distance(pivot) + radius
+    }
+
+    // World-space bounds of the collider over the whole sweep
+    pub fn bounds(&self, collider: &dyn Collider) -> Aabb {
+        let end = Aabb::from_collider(&self.apply(collider, 1.0));
+        collider
+            .aabb()
+            .union(&end)
+            .expand(self.rotation.magnitude() * self.reach(collider) + TOI_TOLERANCE)
+    }
+}
+
+// Convex collider moved rigidly about `pivot`, which is where its body is
+pub struct Transformed<'a> {
+    collider: &'a dyn Collider,
+    pivot: Vec3,
+    translation: Vec3,
+    rotation: Quat,
+}
+
+impl Transformed<'_> {
+    fn transform(&self, x: Vec3) -> Vec3 {
+        self.pivot + self.translation + self.rotation.rotate_vector(x - self.pivot)
+    }
+}
+
+impl Collider for Transformed<'_> {
+    fn support(&self, dir: Vec3) -> Point3<f32> {
+        let p = self
+            .collider
+            .support(self.rotation.invert().rotate_vector(dir));
+        Point3::origin() + self.transform(p.to_vec())
+    }
+    fn to_local(&self, x: Point3<f32>) -> Vec3 {
+        let x = self.pivot
+            + self
+                .rotation
+                .invert()
+                .rotate_vector(x.to_vec() - self.pivot - self.translation);
+        self.collider.to_local(Point3::origin() + x)
+    }
+    fn get_body(&self) -> Rc<RefCell<dyn Body>> {
+        self.collider.get_body()
+    }
+    fn material(&self) -> Material {
+        self.collider.material()
+    }
+    fn get_plane(&self) -> Option<(Vec3, f32)> {
+        let (normal, offset) = self.collider.get_plane()?;
+        let moved = self.rotation.rotate_vector(normal);
+        Some((moved, moved.dot(self.transform(normal * offset))))
+    }
+}
+
+#[derive(Debug, Clone, Copy)]
+pub struct TimeOfImpact {
+    pub t: f32,
+    // World-space witness points at time t
+    pub point_a: Vec3,
+    pub point_b: Vec3,
+    // Unit, from a to b
+    pub normal: Vec3,
+}
+
+// Closest points of two convex colliders, planes are handled exactly since
+// their support points are too far out for GJK. None if they overlap.
+fn separation(a: &dyn Collider, b: &dyn Collider) -> Option<Distance> {
+    if let Some((normal, offset)) = b.get_plane() {
+        let point_a = a.support(-normal).to_vec();
+        let distance = normal.dot(point_a) - offset;
+        return (distance > 0.0).then(|| Distance {
+            distance,
+            point_a,
+            point_b: point_a - normal * distance,
+            axis: -normal,
+        });
+    }
+    if let Some((normal, offset)) = a.get_plane() {
+        let point_b = b.support(-normal).to_vec();
+        let distance = normal.dot(point_b) - offset;
+        return (distance > 0.0).then(|| Distance {
+            distance,
+            point_a: point_b - normal * distance,
+            point_b,
+            axis: normal,
+        });
+    }
+    gjk_distance(a, b)
+}
+
+// Rough contact for colliders that already overlap at the start of the sweep
+fn overlapping(a: &dyn Collider, b: &dyn Collider) -> TimeOfImpact {
+    let normal = if let Some((normal, _)) = b.get_plane() {
+        -normal
+    } else if let Some((normal, _)) = a.get_plane() {
+        normal
+    } else {
+        let axis = b.bounding_sphere().0 - a.bounding_sphere().0;
+        if axis.magnitude2() > f32::EPSILON {
+            axis.normalize()
+        } else {
+            Vec3::unit_y()
+        }
+    };
+
+    TimeOfImpact {
+        t: 0.0,
+        point_a: a.support(normal).to_vec(),
+        point_b: b.support(-normal).to_vec(),
+        normal,
+    }
+}
+
+// Conservative advancement (Mirtich): the distance along the closest points'
+// axis can't shrink faster than the relative linear motion along it plus the
+// angular speed times the reach of each collider, so stepping the distance
+// over that bound never skips past the contact.
+fn convex_time_of_impact(
+    a: &dyn Collider,
+    sweep_a: &Sweep,
+    b: &dyn Collider,
+    sweep_b: &Sweep,
+) -> Option<TimeOfImpact> {
+    let angular = sweep_a.rotation.magnitude() * sweep_a.reach(a)
+        + sweep_b.rotation.magnitude() * sweep_b.reach(b);
+    let mut t = 0.0;
+
+    for _ in 0..64 {
+        let moved_a = sweep_a.apply(a, t);
+        let moved_b = sweep_b.apply(b, t);
+        let Some(d) = separation(&moved_a, &moved_b) else {
+            return (t == 0.0).then(|| overlapping(&moved_a, &moved_b));
+        };
+
+        let hit = TimeOfImpact {
+            t,
+            point_a: d.point_a,
+            point_b: d.point_b,
+            normal: d.axis,
+        };
+        if d.distance < TOI_TOLERANCE {
+            return Some(hit);
+        }
+
+        let rate = (sweep_a.translation - sweep_b.translation).dot(d.axis) + angular;
+        if rate <= 0.0 {
+            return None;
+        }
+        t += (d.distance - 0.5 * TOI_TOLERANCE) / rate;
+        if t > 1.0 {
+            return None;
+        }
+    }
+
+    // Still approaching after every step, the last pose is safe to stop at
+    let moved_a = sweep_a.apply(a, t);
+    let moved_b = sweep_b.apply(b, t);
+    separation(&moved_a, &moved_b).map(|d| TimeOfImpact {
+        t,
+        point_a: d.point_a,
+        point_b: d.point_b,
+        normal: d.axis,
+    })
+}
+
+fn is_static(collider: &dyn Collider) -> bool {
+    collider.get_plane().is_some() || collider.is_concave()
+}
+
+// First time in [0, 1] at which the colliders touch while both sweep, or None
+// if they never do. Colliders overlapping at the start give t = 0 with a rough
+// normal; the contact code gives their actual penetration. Concave colliders
+// are swept against triangle by triangle.
+pub fn time_of_impact(
+    a: &dyn Collider,
+    sweep_a: &Sweep,
+    b: &dyn Collider,
+    sweep_b: &Sweep,
+) -> Option<TimeOfImpact> {
+    if let Some(triangles) = a.get_triangles(&sweep_b.bounds(b)) {
+        if is_static(b) {
+            return None;
+        }
+        let body = a.get_body();
+        return triangles
+            .into_iter()
+            .filter(|[p, q, r]| (q - p).cross(r - p).magnitude2() > f32::EPSILON)
+            .filter_map(|triangle| {
+                convex_time_of_impact(&triangle_prism(&body, triangle), sweep_a, b, sweep_b)
+            })
+            .min_by(|x, y| x.t.total_cmp(&y.t));
+    }
+    if let Some(triangles) = b.get_triangles(&sweep_a.bounds(a)) {
+        if is_static(a) {
+            return None;
+        }
+        let body = b.get_body();
+        return triangles
+            .into_iter()
+            .filter(|[p, q, r]| (q - p).cross(r - p).magnitude2() > f32::EPSILON)
+            .filter_map(|triangle| {
+                convex_time_of_impact(a, sweep_a, &triangle_prism(&body, triangle), sweep_b)
+            })
+            .min_by(|x, y| x.t.total_cmp(&y.t));
+    }
+    if a.get_plane().is_some() && b.get_plane().is_some() {
+        return None;
+    }
+
+    convex_time_of_impact(a, sweep_a, b, sweep_b)
+}
+
+#[cfg(test)]
+mod toi_test {
+    use super::*;
+    use crate::collision::chull::*;
+    use crate::config::*;
+    use crate::cube::*;
+    use crate::inertiatensor::*;
+    use crate::particle::*;
+    use crate::physics::*;
+    use std::f32::consts::PI;
+
+    fn body(pos: Vec3) -> Rc<RefCell<dyn Body>> {
+        Rc::new(RefCell::new(Particle::new(pos, 1.0)))
+    }
+
+    #[test]
+    fn translation() {
+        let b1 = body(Vec3::zero());
+        let b2 = body(vec3(3.0, 0.0, 0.0));
+        let sphere = SphereCollider::new(&b1, 0.5);
+        let cube = PolyhedraCollider::new(&b2, cube_polyhedra(1.0));
+
+        let still = Sweep::zero();
+        let hit = time_of_impact(
+            &sphere,
+            &Sweep::translation(vec3(10.0, 0.0, 0.0)),
+            &cube,
+            &still,
+        )
+        .unwrap();
+        assert!((hit.t - 0.2).abs() < 1e-3);
+        assert!((hit.normal - Vec3::unit_x()).magnitude() < 1e-3);
+        assert!((hit.point_b - vec3(2.5, 0.0, 0.0)).magnitude() < 1e-2);
+
+        // Both moving towards each other
+        let hit = time_of_impact(
+            &sphere,
+            &Sweep::translation(vec3(1.0, 0.0, 0.0)),
+            &cube,
+            &Sweep::translation(vec3(-1.5, 0.0, 0.0)),
+        )
+        .unwrap();
+        assert!((hit.t - 0.8).abs() < 1e-3);
+
+        // Passing by, falling short and moving away
+        for sweep in [
+            vec3(0.0, 10.0, 0.0),
+            vec3(1.0, 0.0, 0.0),
+            vec3(-10.0, 0.0, 0.0),
+        ] {
+            assert!(time_of_impact(&sphere, &Sweep::translation(sweep), &cube, &still).is_none());
+        }
+
+        // Already touching
+        b1.as_ref().borrow_mut().update_pos(vec3(2.2, 0.0, 0.0));
+        let hit = time_of_impact(
+            &sphere,
+            &Sweep::translation(vec3(1.0, 0.0, 0.0)),
+            &cube,
+            &still,
+        )
+        .unwrap();
+        assert_eq!(hit.t, 0.0);
+        assert!((hit.normal - Vec3::unit_x()).magnitude() < 1e-3);
+    }
+
+    #[test]
+    fn rotation() {
+        // Bar turning about its middle until a corner meets the ground y = -1
+        let bar = Rc::new(RefCell::new(RigidBody::new(
+            Vec3::zero(),
+            Quat::one(),
+            1.0,
+            zeroinertia_mass(),
+        ))) as Rc<RefCell<dyn Body>>;
+        let bar = PolyhedraCollider::new(&bar, box_polyhedra(vec3(2.0, 0.1, 0.1)));
+        let ground = PlaneCollider::new(Vec3::unit_y(), -1.0);
+
+        let sweep = Sweep::new(Vec3::zero(), vec3(0.0, 0.0, -PI / 2.0));
+        let hit = time_of_impact(&bar, &sweep, &ground, &Sweep::zero()).unwrap();
+        let angle = (1.0 / 4.01f32.sqrt()).asin() - 0.1f32.atan2(2.0);
+        assert!((hit.t - angle / (PI / 2.0)).abs() < 2e-3);
+        assert!((hit.normal + Vec3::unit_y()).magnitude() < 1e-4);
+        assert!(hit.point_a.x > 1.7 && (hit.point_a.y + 1.0).abs() < 1e-3);
+
+        // Against a box, the pose at t is touching but not overlapping
+        let b2 = body(vec3(1.0, 1.5, 0.0));
+        let cube = PolyhedraCollider::new(&b2, cube_polyhedra(1.0));
+        let sweep = Sweep::new(Vec3::zero(), vec3(0.0, 0.0, PI / 2.0));
+        let hit = time_of_impact(&bar, &sweep, &cube, &Sweep::zero()).unwrap();
+        assert!(hit.t > 0.0 && hit.t < 1.0);
+        let d = gjk_distance(&sweep.apply(&bar, hit.t), &cube).unwrap();
+        assert!(d.distance < TOI_TOLERANCE);
+    }
+
+    #[test]
+    fn static_colliders() {
+        let ball = body(vec3(1.0, 2.0, 1.0));
+        let sphere = SphereCollider::new(&ball, 0.5);
+        let down = Sweep::translation(vec3(0.0, -4.0, 0.0));
+
+        let ground = PlaneCollider::new(Vec3::unit_y(), 0.0);
+        let hit = time_of_impact(&sphere, &down, &ground, &Sweep::zero()).unwrap();
+        assert!((hit.t - 0.375).abs() < 1e-3);
+        assert!((hit.point_b - vec3(1.0, 0.0, 1.0)).magnitude() < 1e-3);
+
+        let mesh = TriMeshCollider::from_cpu_mesh(
+            &CpuMesh::square(),
+            Mat4::from_scale(5.0) * Mat4::from_angle_x(Deg(-90.0)),
+        );
+        let hit = time_of_impact(&mesh, &Sweep::zero(), &sphere, &down).unwrap();
+        assert!((hit.t - 0.375).abs() < 1e-3);
+        assert!((hit.normal - Vec3::unit_y()).magnitude() < 1e-2);
+
+        let aside = Sweep::translation(vec3(20.0, -4.0, 0.0));
+        assert!(time_of_impact(&sphere, &aside, &mesh, &Sweep::zero()).is_none());
+        assert!(time_of_impact(&ground, &Sweep::zero(), &mesh, &Sweep::zero()).is_none());
+    }
+
+    #[test]
+    fn physics_shape_cast() {
+        let mut physics = Physics::new(vec3(0.0, -9.8, 0.0), 1, 1);
+        physics.add_collider(PlaneCollider::new(Vec3::unit_y(), 0.0));
+        let ball = physics.add_body(Particle::new(vec3(0.0, 2.0, 0.0), 1.0));
+        let ball = ball as Rc<RefCell<dyn Body>>;
+        physics.add_collider(SphereCollider::new(&ball, 0.5));
+
+        let probe = body(vec3(0.0, 5.0, 0.0));
+        let cube = PolyhedraCollider::new(&probe, cube_polyhedra(1.0));
+        let down = Sweep::translation(vec3(0.0, -10.0, 0.0));
+
+        let (hit_body, hit) = physics.cast_shape(&cube, &down).unwrap();
+        assert!(Rc::ptr_eq(&hit_body, &ball));
+        assert!((hit.t - 0.2).abs() < 1e-3);
+
+        probe.as_ref().borrow_mut().update_pos(vec3(3.0, 0.0, 0.0));
+        let (_, hit) = physics.cast_shape(&cube, &down).unwrap();
+        assert!((hit.t - 0.45).abs() < 1e-3);
+        assert!(physics
+            .cast_shape(&cube, &Sweep::translation(vec3(0.0, 10.0, 0.0)))
+            .is_none());
+    }
+
+    #[test]
+    fn continuous_collision() {
+        // Small cube dropped fast onto a thin static slab
+        let fall = |ccd: bool| {
+            let mut physics = Physics::new(vec3(0.0, -9.8, 0.0), 1, 4);
+            let slab = physics.add_body(RigidBody::new(
+                Vec3::zero(),
+                Quat::one(),
+                0.0,
+                zeroinertia_mass(),
+            ));
+            physics.add_collider(PolyhedraCollider::new(
+                &(slab as Rc<RefCell<dyn Body>>),
+                box_polyhedra(vec3(2.0, 0.05, 2.0)),
+            ));
+
+            let mut cube =
+                RigidBody::new(vec3(0.0, 2.0, 0.0), Quat::one(), 1.0, cubeinertia_mass(0.2));
+            cube.set_vel(vec3(0.0, -100.0, 0.0));
+            cube.set_ccd(ccd);
+            let cube = physics.add_body(cube);
+            physics.add_collider(PolyhedraCollider::new(
+                &(cube.clone() as Rc<RefCell<dyn Body>>),
+                cube_polyhedra(0.2),
+            ));
+
+            // Lowest the cube gets over a few frames
+            (0..10)
+                .map(|_| {
+                    physics.update(1.0 / 60.0);
+                    cube.as_ref().borrow().pos().y
+                })
+                .fold(f32::MAX, f32::min)
+        };
+
+        assert!(fall(false) < -1.0);
+        // Resting on the slab would put it at 0.15
+        assert!(fall(true) > 0.13);
+
+        // Dropped cubes piling up never get faster than the fall made them
+        let mut physics = Physics::new(vec3(0.0, -5.0, 0.0), SUBS, ITER);
+        physics.add_collider(PlaneCollider::new(vec3(0.0, 1.0, 0.0), -0.5));
+        let cubes: Vec<_> = (0..3)
+            .map(|i| {
+                let mut cube = RigidBody::new(
+                    vec3(0.0, 1.0 + 2.0 * i as f32, 0.01 * i as f32),
+                    Quat::one(),
+                    1.0,
+                    cubeinertia_mass(1.0),
+                );
+                cube.set_ccd(true);
+                let cube = physics.add_body(cube);
+                physics.add_collider(PolyhedraCollider::new(
+                    &(cube.clone() as Rc<RefCell<dyn Body>>),
+                    cube_polyhedra(1.0),
+                ));
+                cube
+            })
+            .collect();
+        let fastest = (0..300)
+            .map(|_| {
+                physics.update(1.0 / 60.0);
+                cubes
+                    .iter()
+                    .map(|cube| cube.as_ref().borrow().vel().magnitude())
+                    .fold(0.0, f32::max)
+            })
+            .fold(0.0, f32::max);
+        // Falling from the top, at y = 5, onto the plane
+        assert!(fastest < (2.0 * 5.0 * 5.0f32).sqrt());
+    }
+}
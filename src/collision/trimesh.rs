@@ -0,0 +1,155 @@
+use super::aabb::*;
+use super::broadphase::*;
+use super::bvh::*;
+use super::collider::*;
+use super::raycast::*;
+use crate::body::*;
+use crate::cube::*;
+use crate::inertiatensor::*;
+use crate::material::Material;
+use std::cell::RefCell;
+use std::rc::Rc;
+use std::vec::Vec;
+use three_d::*;
+
+// How far each triangle is extruded backwards when it is handed to the convex
+// contact code
+pub const TRIANGLE_PRISM_DEPTH: f32 = 0.25;
+
+// Convex prism under a world-space triangle (CCW seen from its front side),
+// attached to `body`, which has to sit at the origin without rotation. The
+// faces are wound like cube_polyhedra.
+pub fn triangle_prism(body: &Rc<RefCell<dyn Body>>, triangle: [Vec3; 3]) -> PolyhedraCollider {
+    let [a, b, c] = triangle;
+    let n = (b - a).cross(c - a).normalize() * TRIANGLE_PRISM_DEPTH;
+
+    PolyhedraCollider::new(
+        body,
+        (
+            vec![a, b, c, a - n, b - n, c - n],
+            vec![
+                vec![2, 1, 0],
+                vec![4, 5, 3],
+                vec![1, 4, 3, 0],
+                vec![2, 5, 4, 1],
+                vec![0, 3, 5, 2],
+            ],
+        ),
+    )
+}
+
+// Static concave triangle mesh for level geometry. Vertices are given in
+// world space and the collider owns an immovable body, like `PlaneCollider`.
+pub struct TriMeshCollider {
+    body: Rc<RefCell<dyn Body>>,
+    vertices: Vec<Vec3>,
+    triangles: Vec<[usize; 3]>,
+    tree: DynamicTree,
+    aabb: Aabb,
+    material: Material,
+}
+
+impl TriMeshCollider {
+    pub fn new(vertices: Vec<Vec3>, triangles: Vec<[usize; 3]>) -> Self {
+        let mut tree = DynamicTree::new(0.0);
+        for (i, t) in triangles.iter().enumerate() {
+            tree.insert(
+                i,
+                Aabb::from_points(&[vertices[t[0]], vertices[t[1]], vertices[t[2]]])
+                    .expand(TRIANGLE_PRISM_DEPTH),
+            );
+        }
+
+        Self {
+            body: Rc::new(RefCell::new(RigidBody::new(
+                Vec3::zero(),
+                Quat::one(),
+                0.0,
+                zeroinertia_mass(),
+            ))),
+            aabb: Aabb::from_points(&vertices).expand(TRIANGLE_PRISM_DEPTH),
+            vertices,
+            triangles,
+            tree,
+            material: Material::default(),
+        }
+    }
+
+    pub fn from_cpu_mesh(mesh: &CpuMesh, transform: Mat4) -> Self {
+        let vertices: Vec<Vec3> = mesh
+            .positions
+            .to_f32()
+            .into_iter()
+            .map(|v| (transform * v.extend(1.0)).truncate())
+            .collect();
+        let indices: Vec<usize> = match mesh.indices.to_u32() {
+            Some(indices) => indices.into_iter().map(|i| i as usize).collect(),
+            None => (0..vertices.len()).collect(),
+        };
+        let triangles = indices
+            .chunks_exact(3)
+            .map(|t| [t[0], t[1], t[2]])
+            .collect();
+
+        Self::new(vertices, triangles)
+    }
+
+    pub fn triangle(&self, i: usize) -> [Vec3; 3] {
+        self.triangles[i].map(|v| self.vertices[v])
+    }
+
+    pub fn with_material(mut self, material: Material) -> Self {
+        self.material = material;
+        self
+    }
+}
+
+impl Collider for TriMeshCollider {
+    fn support(&self, dir: Vec3) -> Point3<f32> {
+        let mut maximum = f32::MIN;
+        let mut maxdir = Vec3::zero();
+        for point in &self.vertices {
+            let dot = point.dot(dir);
+            if dot > maximum {
+                maximum = dot;
+                maxdir = *point;
+            }
+        }
+
+        Point3::origin() + maxdir
+    }
+    fn to_local(&self, x: Point3<f32>) -> Vec3 {
+        x.to_vec()
+    }
+    fn get_body(&self) -> Rc<RefCell<dyn Body>> {
+        self.body.clone()
+    }
+    fn material(&self) -> Material {
+        self.material
+    }
+    fn is_concave(&self) -> bool {
+        true
+    }
+    fn get_triangles(&self, aabb: &Aabb) -> Option<Vec<[Vec3; 3]>> {
+        Some(
+            self.tree
+                .query_aabb(aabb)
+                .into_iter()
+                .map(|i| self.triangle(i))
+                .collect(),
+        )
+    }
+
+    fn aabb(&self) -> Aabb {
+        self.aabb
+    }
+    fn cast_ray(&self, origin: Vec3, dir: Vec3, max_t: f32) -> Option<RayHit> {
+        let triangles: Vec<[Vec3; 3]> = self
+            .tree
+            .query_ray(origin, dir, max_t)
+            .into_iter()
+            .map(|i| self.triangle(i))
+            .collect();
+        ray_triangles(origin, dir, max_t, &triangles)
+    }
+}
@@ -9,3 +9,29 @@ pub const SUBS: usize = 20;
 
 pub const DAMP: f32 = 0.95;
 pub const ROT_DAMP: f32 = 0.95;
+
+// How far the broadphase grows the swept bounds of every collider
+pub const BROADPHASE_MARGIN: f32 = 0.05;
+
+// How far along its path a body stopped by continuous collision detection is
+// carried past the time of impact, so that the contact shows up as a regular
+// collision
+pub const CCD_SLOP: f32 = 0.01;
+
+// Default material: friction coefficients of contacts at rest and sliding,
+// bounciness, and softness of contacts
+pub const STATIC_FRICTION: f32 = 1.0;
+pub const DYNAMIC_FRICTION: f32 = 0.9;
+pub const RESTITUTION: f32 = 0.9;
+pub const CONTACT_COMPLIANCE: f32 = 0.0000001;
+// Resistance to rolling and to spinning about the contact normal, as the
+// length of the lever the normal force works through
+pub const ROLLING_FRICTION: f32 = 0.0;
+pub const TORSIONAL_FRICTION: f32 = 0.0;
+
+// Contacts coming in slower than this many times |g|*dt don't bounce, so that
+// resting bodies settle instead of hopping on every substep
+pub const RESTITUTION_THRESHOLD: f32 = 2.0;
+
+// Share of last step's contact lambda a matched contact starts from
+pub const WARM_START: f32 = 0.8;
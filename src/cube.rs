@@ -2,6 +2,7 @@ use crate::config::*;
 use crate::body::*;
 use three_d::*;
 use crate::collision::collider::Collider;
+use crate::inertiatensor::*;
 
 #[derive(Clone)]
 pub struct RigidBody {
@@ -22,6 +23,10 @@ pub struct RigidBody {
     invmass: f32,
     invinertia: Mat3,
     inertia: Mat3,
+    // Rotation from the principal axes to the body frame
+    inertia_frame: Quat,
+
+    ccd: bool,
 }
 
 impl Body for RigidBody {
@@ -31,9 +36,12 @@ impl Body for RigidBody {
 
 impl RigidBody {
     pub fn new(pos: Vec3, apos: Quat, invmass: f32, inertia_mass: (Mat3, Mat3)) -> Self {
-        let (inertia, invinertia) = if !invmass.is_zero() {
-            (inertia_mass.0/invmass, inertia_mass.1*invmass)
-        } else {(Mat3::zero(), Mat3::zero())};
+        let (moments, inertia_frame) = if !invmass.is_zero() {
+            principal_axes(inertia_mass.0/invmass)
+        } else {(Vec3::zero(), Quat::one())};
+        let inverse = |m: f32| if m > f32::EPSILON {1.0/m} else {0.0};
+        let inertia = Mat3::from_diagonal(moments);
+        let invinertia = Mat3::from_diagonal(vec3(inverse(moments.x), inverse(moments.y), inverse(moments.z)));
 
         Self {
             pos_prev: pos,
@@ -53,6 +61,31 @@ impl RigidBody {
             invmass,
             invinertia,
             inertia,
+            inertia_frame,
+
+            ccd: false,
         }
     }
+
+    pub fn set_ccd(&mut self, ccd: bool) {
+        self.ccd = ccd;
+    }
+    // Body for a polyhedron placed with its local origin at `pos`, and the
+    // polyhedron moved so that the center of mass the body sits at is its
+    // origin, ready for PolyhedraCollider::new.
+    pub fn from_polyhedra(
+        pos: Vec3,
+        apos: Quat,
+        polyhedra: &(Vec<Vec3>, Vec<Vec<usize>>),
+        density: f32
+    ) -> (Self, (Vec<Vec3>, Vec<Vec<usize>>)) {
+        let properties = polyhedra_mass_properties(&polyhedra.0, &polyhedra.1, density);
+        let invmass = if properties.mass > 0.0 {1.0/properties.mass} else {0.0};
+        let vertices = polyhedra.0.iter().map(|v| v - properties.center).collect();
+
+        (
+            Self::new(pos + apos.rotate_vector(properties.center), apos, invmass, properties.inertia_mass()),
+            (vertices, polyhedra.1.clone()),
+        )
+    }
 }
@@ -1,19 +1,302 @@
+use std::f32::consts::PI;
 use three_d::*;
 
 pub fn cubeinertia_mass(sidelen: f32) -> (Mat3, Mat3) {
-    let result = sidelen*sidelen*Mat3::from_cols(
-        vec3(2.0/3.0, -0.25, -0.25),
-        vec3(-0.25, 2.0/3.0, -0.25),
-        vec3(-0.25, -0.25, 2.0/3.0)
-    );
-
-    (result, result.invert().unwrap())
+    uniform_inertia(sidelen * sidelen / 6.0)
 }
 
 pub fn sphereinertia_mass(radius: f32) -> (Mat3, Mat3) {
-    (5.0/2.0*radius*radius*Mat3::one(), 2.0/5.0/radius/radius*Mat3::one())
+    uniform_inertia(2.0 / 5.0 * radius * radius)
 }
 
 pub fn zeroinertia_mass() -> (Mat3, Mat3) {
     (Mat3::zero(), Mat3::zero())
 }
+
+pub fn boxinertia_mass(half_extents: Vec3) -> (Mat3, Mat3) {
+    let h2 = vec3(
+        half_extents.x * half_extents.x,
+        half_extents.y * half_extents.y,
+        half_extents.z * half_extents.z,
+    );
+    diagonal_inertia(vec3(h2.y + h2.z, h2.x + h2.z, h2.x + h2.y) / 3.0)
+}
+
+pub fn tetrahedroninertia_mass(edgelen: f32) -> (Mat3, Mat3) {
+    uniform_inertia(edgelen * edgelen / 20.0)
+}
+
+pub fn octahedroninertia_mass(edgelen: f32) -> (Mat3, Mat3) {
+    uniform_inertia(edgelen * edgelen / 10.0)
+}
+
+pub fn icosahedroninertia_mass(edgelen: f32) -> (Mat3, Mat3) {
+    uniform_inertia(edgelen * edgelen * (3.0 + 5.0f32.sqrt()) / 20.0)
+}
+
+pub fn dodecahedroninertia_mass(edgelen: f32) -> (Mat3, Mat3) {
+    uniform_inertia(edgelen * edgelen * (95.0 + 39.0 * 5.0f32.sqrt()) / 300.0)
+}
+
+// Polar moment of a regular polygon with circumradius `radius`, per unit mass
+fn polygon_moment(sides: usize, radius: f32) -> f32 {
+    let c = (PI / sides as f32).cos();
+    radius * radius * (1.0 + 2.0 * c * c) / 6.0
+}
+
+// Matches prism_polyhedra: axis along y, centered on the origin
+pub fn prisminertia_mass(sides: usize, radius: f32, height: f32) -> (Mat3, Mat3) {
+    let k = polygon_moment(sides, radius);
+    let transverse = k / 2.0 + height * height / 12.0;
+    diagonal_inertia(vec3(transverse, k, transverse))
+}
+
+// Matches pyramid_polyhedra: apex up the y axis, about the centroid
+pub fn pyramidinertia_mass(sides: usize, radius: f32, height: f32) -> (Mat3, Mat3) {
+    let k = polygon_moment(sides, radius);
+    let transverse = 3.0 * k / 10.0 + 3.0 * height * height / 80.0;
+    diagonal_inertia(vec3(transverse, 3.0 * k / 5.0, transverse))
+}
+
+pub fn cylinderinertia_mass(radius: f32, height: f32, segments: usize) -> (Mat3, Mat3) {
+    prisminertia_mass(segments, radius, height)
+}
+
+pub fn coneinertia_mass(radius: f32, height: f32, segments: usize) -> (Mat3, Mat3) {
+    pyramidinertia_mass(segments, radius, height)
+}
+
+fn diagonal_inertia(moments: Vec3) -> (Mat3, Mat3) {
+    (
+        Mat3::from_diagonal(moments),
+        Mat3::from_diagonal(vec3(1.0 / moments.x, 1.0 / moments.y, 1.0 / moments.z)),
+    )
+}
+
+fn uniform_inertia(moment: f32) -> (Mat3, Mat3) {
+    (moment * Mat3::one(), 1.0 / moment * Mat3::one())
+}
+
+// Eigen decomposition of a symmetric inertia tensor by Jacobi rotations.
+// Returns the principal moments and the rotation taking the principal axes
+// to the frame the tensor was given in, so that
+// inertia = R * diag(moments) * R^T with R = Mat3::from(rotation).
+pub fn principal_axes(inertia: Mat3) -> (Vec3, Quat) {
+    let mut a = inertia;
+    let mut v = Mat3::one();
+
+    for _ in 0..32 {
+        let off = a[1][0] * a[1][0] + a[2][0] * a[2][0] + a[2][1] * a[2][1];
+        let diag = a[0][0] * a[0][0] + a[1][1] * a[1][1] + a[2][2] * a[2][2];
+        if off <= diag * 1e-14 {
+            break;
+        }
+
+        for (p, q) in [(0, 1), (0, 2), (1, 2)] {
+            if a[q][p].abs() < f32::MIN_POSITIVE {
+                continue;
+            }
+            let theta = (a[q][q] - a[p][p]) / (2.0 * a[q][p]);
+            let t = theta.signum() / (theta.abs() + (theta * theta + 1.0).sqrt());
+            let c = 1.0 / (t * t + 1.0).sqrt();
+            let s = t * c;
+
+            let mut j = Mat3::one();
+            j[p][p] = c;
+            j[q][q] = c;
+            j[q][p] = s;
+            j[p][q] = -s;
+
+            a = j.transpose() * a * j;
+            v = v * j;
+        }
+    }
+
+    // Keep it a proper rotation
+    if v.determinant() < 0.0 {
+        v.z = -v.z;
+    }
+
+    (vec3(a[0][0], a[1][1], a[2][2]), Quat::from(v).normalize())
+}
+
+pub struct MassProperties {
+    pub mass: f32,
+    // In the frame the vertices are given in
+    pub center: Vec3,
+    // About the center of mass
+    pub inertia: Mat3,
+}
+
+impl MassProperties {
+    // Per unit mass inertia and its inverse, as RigidBody::new takes them
+    pub fn inertia_mass(&self) -> (Mat3, Mat3) {
+        let inertia = self.inertia / self.mass;
+        (inertia, inertia.invert().unwrap_or(Mat3::zero()))
+    }
+}
+
+// Mass, center of mass and inertia tensor of a closed polyhedron of uniform
+// density. The faces are fanned into triangles and every triangle spans a
+// tetrahedron with the origin, whose signed volume integrals add up to the
+// ones of the whole solid (divergence theorem). Works with either winding.
+pub fn polyhedra_mass_properties(
+    vertices: &[Vec3],
+    faces: &[Vec<usize>],
+    density: f32,
+) -> MassProperties {
+    let mut volume = 0.0;
+    let mut first = Vec3::zero();
+    // Second moment integral of x x^T
+    let mut covariance = Mat3::zero();
+
+    for face in faces {
+        for k in 1..(face.len() - 1) {
+            let a = vertices[face[0]];
+            let b = vertices[face[k]];
+            let c = vertices[face[k + 1]];
+            let det = a.dot(b.cross(c));
+            let sum = a + b + c;
+
+            volume += det / 6.0;
+            first += det / 24.0 * sum;
+            covariance += det / 120.0 * (outer(a, a) + outer(b, b) + outer(c, c) + outer(sum, sum));
+        }
+    }
+
+    if volume < 0.0 {
+        volume = -volume;
+        first = -first;
+        covariance = -covariance;
+    }
+
+    let center = first / volume;
+    let covariance = (covariance - volume * outer(center, center)) * density;
+    let trace = covariance.x.x + covariance.y.y + covariance.z.z;
+
+    MassProperties {
+        mass: volume * density,
+        center,
+        inertia: trace * Mat3::one() - covariance,
+    }
+}
+
+fn outer(a: Vec3, b: Vec3) -> Mat3 {
+    Mat3::from_cols(a * b.x, a * b.y, a * b.z)
+}
+
+#[cfg(test)]
+mod inertiatensor_test {
+    use super::*;
+    use crate::body::*;
+    use crate::collision::chull::*;
+    use crate::cube::*;
+
+    fn assert_close(a: Mat3, b: Mat3) {
+        for i in 0..3 {
+            for j in 0..3 {
+                assert!(
+                    (a[i][j] - b[i][j]).abs() < 1e-3 * b[i][i].abs().max(1.0),
+                    "{:?} {:?}",
+                    a,
+                    b
+                );
+            }
+        }
+    }
+
+    #[test]
+    fn matches_analytic() {
+        let shapes = [
+            (cube_polyhedra(1.5), cubeinertia_mass(1.5)),
+            (
+                box_polyhedra(vec3(0.5, 1.0, 2.0)),
+                boxinertia_mass(vec3(0.5, 1.0, 2.0)),
+            ),
+            (tetrahedron_polyhedra(1.2), tetrahedroninertia_mass(1.2)),
+            (octahedron_polyhedra(1.2), octahedroninertia_mass(1.2)),
+            (icosahedron_polyhedra(1.2), icosahedroninertia_mass(1.2)),
+            (dodecahedron_polyhedra(1.2), dodecahedroninertia_mass(1.2)),
+            (prism_polyhedra(5, 0.7, 2.0), prisminertia_mass(5, 0.7, 2.0)),
+            (
+                pyramid_polyhedra(6, 0.7, 2.0),
+                pyramidinertia_mass(6, 0.7, 2.0),
+            ),
+        ];
+
+        for ((vertices, faces), (inertia, invinertia)) in &shapes {
+            let properties = polyhedra_mass_properties(vertices, faces, 3.0);
+            assert!(properties.center.magnitude() < 1e-4);
+
+            let (i, inv) = properties.inertia_mass();
+            assert_close(i, *inertia);
+            assert_close(inv, *invinertia);
+        }
+    }
+
+    #[test]
+    fn off_center() {
+        // Unit cube with a corner at the origin: mass 2, center at the middle
+        let (vertices, faces) = cube_polyhedra(1.0);
+        let vertices: Vec<Vec3> = vertices.iter().map(|v| v + vec3(0.5, 0.5, 0.5)).collect();
+        let properties = polyhedra_mass_properties(&vertices, &faces, 2.0);
+
+        assert!((properties.mass - 2.0).abs() < 1e-5);
+        assert!((properties.center - vec3(0.5, 0.5, 0.5)).magnitude() < 1e-5);
+        assert_close(properties.inertia, 2.0 * cubeinertia_mass(1.0).0);
+
+        let (body, shape) =
+            RigidBody::from_polyhedra(vec3(1.0, 0.0, 0.0), Quat::one(), &(vertices, faces), 2.0);
+        assert!((body.pos() - vec3(1.5, 0.5, 0.5)).magnitude() < 1e-5);
+        assert!((body.invmass() - 0.5).abs() < 1e-5);
+        // The shape comes back centered on the body
+        let properties = polyhedra_mass_properties(&shape.0, &shape.1, 2.0);
+        assert!(properties.center.magnitude() < 1e-5);
+        for (v, w) in shape.0.iter().zip(cube_polyhedra(1.0).0) {
+            assert!((v - w).magnitude() < 1e-5);
+        }
+    }
+
+    #[test]
+    fn principal_axes_recover_moments() {
+        let r = Mat3::from(Quat::from_axis_angle(
+            vec3(1.0, 2.0, 3.0).normalize(),
+            Rad(0.7),
+        ));
+        let inertia = r * Mat3::from_diagonal(vec3(1.0, 2.0, 3.0)) * r.transpose();
+
+        let (moments, frame) = principal_axes(inertia);
+        let mut sorted = [moments.x, moments.y, moments.z];
+        sorted.sort_by(f32::total_cmp);
+        assert!((vec3(sorted[0], sorted[1], sorted[2]) - vec3(1.0, 2.0, 3.0)).magnitude() < 1e-4);
+
+        let f = Mat3::from(frame);
+        assert_close(f * Mat3::from_diagonal(moments) * f.transpose(), inertia);
+    }
+
+    #[test]
+    fn free_rotation_keeps_angular_momentum() {
+        let apos = Quat::from_axis_angle(vec3(1.0, 1.0, 0.0).normalize(), Rad(0.5));
+        let mut body = RigidBody::new(
+            Vec3::zero(),
+            apos,
+            1.0,
+            boxinertia_mass(vec3(0.2, 0.5, 1.0)),
+        );
+        body.set_avel(vec3(3.0, 0.2, 0.1));
+
+        let r = Mat3::from(apos);
+        assert_close(
+            body.inertia_world(),
+            r * boxinertia_mass(vec3(0.2, 0.5, 1.0)).0 * r.transpose(),
+        );
+
+        let momentum = body.inertia_world() * body.avel();
+        for _ in 0..1000 {
+            body.predict(0.001);
+            body.update(0.001);
+        }
+        let drift = body.inertia_world() * body.avel() - momentum;
+        assert!(drift.magnitude() < 0.02 * momentum.magnitude());
+    }
+}
@@ -9,6 +9,7 @@ pub mod cube;
 pub mod particle;
 pub mod particle_constraint;
 pub mod inertiatensor;
+pub mod material;
 pub mod physics;
 pub mod collision;
 pub mod timestep_schedule;
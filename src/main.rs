@@ -9,6 +9,7 @@ mod cube;
 mod particle;
 mod particle_constraint;
 mod inertiatensor;
+mod material;
 mod physics;
 mod collision;
 mod timestep_schedule;
@@ -0,0 +1,405 @@
+use crate::config::*;
+
+// How the values of two touching materials are merged. When the two sides ask
+// for different rules the later one in this list wins.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+pub enum CombineRule {
+    Average,
+    Min,
+    Multiply,
+    Max,
+}
+
+impl CombineRule {
+    pub fn combine(self, a: f32, b: f32) -> f32 {
+        match self {
+            CombineRule::Average => 0.5 * (a + b),
+            CombineRule::Min => a.min(b),
+            CombineRule::Multiply => a * b,
+            CombineRule::Max => a.max(b),
+        }
+    }
+}
+
+// How a material bounces
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum RestitutionMode {
+    // Bounces by the restitution coefficient
+    Coefficient,
+    // Bounces back at the speed it came in
+    Elastic,
+    // Never bounces
+    Inelastic,
+}
+
+impl RestitutionMode {
+    // Mode of a contact between the two. Inelastic beats everything and
+    // Elastic beats Coefficient: anything hitting clay stops, even a
+    // superball, and anything else hitting a superball bounces back fully.
+    pub fn combine(self, other: RestitutionMode) -> RestitutionMode {
+        match (self, other) {
+            (RestitutionMode::Inelastic, _) | (_, RestitutionMode::Inelastic) => {
+                RestitutionMode::Inelastic
+            }
+            (RestitutionMode::Elastic, _) | (_, RestitutionMode::Elastic) => {
+                RestitutionMode::Elastic
+            }
+            (RestitutionMode::Coefficient, RestitutionMode::Coefficient) => {
+                RestitutionMode::Coefficient
+            }
+        }
+    }
+}
+
+// Surface properties of a collider
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Material {
+    pub static_friction: f32,
+    pub dynamic_friction: f32,
+    pub restitution: f32,
+    pub rolling_friction: f32,
+    pub torsional_friction: f32,
+    // Contact softness, in the units of the XPBD compliance
+    pub compliance: f32,
+    // Resistance of a soft contact to moving along its normal
+    pub damping: f32,
+    pub friction_combine: CombineRule,
+    pub restitution_combine: CombineRule,
+    pub restitution_mode: RestitutionMode,
+}
+
+impl Default for Material {
+    fn default() -> Self {
+        Self {
+            static_friction: STATIC_FRICTION,
+            dynamic_friction: DYNAMIC_FRICTION,
+            restitution: RESTITUTION,
+            rolling_friction: ROLLING_FRICTION,
+            torsional_friction: TORSIONAL_FRICTION,
+            // Each side brings half, see combine
+            compliance: 0.5 * CONTACT_COMPLIANCE,
+            damping: 0.0,
+            friction_combine: CombineRule::Average,
+            restitution_combine: CombineRule::Average,
+            restitution_mode: RestitutionMode::Coefficient,
+        }
+    }
+}
+
+impl Material {
+    pub fn new(static_friction: f32, dynamic_friction: f32, restitution: f32) -> Self {
+        Self {
+            static_friction,
+            dynamic_friction,
+            restitution,
+            ..Self::default()
+        }
+    }
+
+    pub fn with_compliance(mut self, compliance: f32, damping: f32) -> Self {
+        self.compliance = compliance;
+        self.damping = damping;
+        self
+    }
+
+    pub fn with_rolling_friction(mut self, rolling: f32, torsional: f32) -> Self {
+        self.rolling_friction = rolling;
+        self.torsional_friction = torsional;
+        self
+    }
+
+    pub fn with_combine(mut self, friction: CombineRule, restitution: CombineRule) -> Self {
+        self.friction_combine = friction;
+        self.restitution_combine = restitution;
+        self
+    }
+
+    pub fn with_restitution_mode(mut self, mode: RestitutionMode) -> Self {
+        self.restitution_mode = mode;
+        self
+    }
+
+    // Restitution coefficient the mode works out to
+    pub fn bounce(&self) -> f32 {
+        match self.restitution_mode {
+            RestitutionMode::Coefficient => self.restitution,
+            RestitutionMode::Elastic => 1.0,
+            RestitutionMode::Inelastic => 0.0,
+        }
+    }
+
+    // Slippery against anything it touches
+    pub fn ice() -> Self {
+        Self::new(0.05, 0.03, 0.1).with_combine(CombineRule::Min, CombineRule::Average)
+    }
+
+    // Grippy, and bouncy against anything it touches
+    pub fn rubber() -> Self {
+        Self::new(1.0, 0.8, 0.8).with_combine(CombineRule::Max, CombineRule::Max)
+    }
+
+    pub fn wood() -> Self {
+        Self::new(0.5, 0.4, 0.3)
+    }
+
+    // Material of a contact between the two. The contact is as soft as both
+    // sides together, like two springs in series, so two default materials
+    // make a contact of CONTACT_COMPLIANCE. Damping is averaged.
+    pub fn combine(&self, other: &Material) -> Material {
+        let friction = self.friction_combine.max(other.friction_combine);
+        let restitution = self.restitution_combine.max(other.restitution_combine);
+        Material {
+            static_friction: friction.combine(self.static_friction, other.static_friction),
+            dynamic_friction: friction.combine(self.dynamic_friction, other.dynamic_friction),
+            restitution: restitution.combine(self.restitution, other.restitution),
+            rolling_friction: friction.combine(self.rolling_friction, other.rolling_friction),
+            torsional_friction: friction.combine(self.torsional_friction, other.torsional_friction),
+            compliance: self.compliance + other.compliance,
+            damping: 0.5 * (self.damping + other.damping),
+            friction_combine: friction,
+            restitution_combine: restitution,
+            restitution_mode: self.restitution_mode.combine(other.restitution_mode),
+        }
+    }
+}
+
+#[cfg(test)]
+mod material_test {
+    use super::Material;
+    use super::*;
+    use crate::body::*;
+    use crate::collision::chull::*;
+    use crate::collision::collider::*;
+    use crate::constraint::*;
+    use crate::cube::*;
+    use crate::inertiatensor::*;
+    use crate::physics::*;
+    use crate::rigidbody_constraint::*;
+    use std::cell::RefCell;
+    use std::rc::Rc;
+    use three_d::*;
+
+    // World under gravity with a plane of the given material through the origin
+    fn ground(normal: Vec3, material: Material) -> Physics {
+        let mut physics = Physics::new(vec3(0.0, -9.8, 0.0), SUBS, ITER);
+        physics.add_collider(PlaneCollider::new(normal, 0.0).with_material(material));
+        physics
+    }
+
+    // Ball of radius 0.5 and mass 1 at pos
+    fn ball(physics: &mut Physics, pos: Vec3, material: Material) -> Rc<RefCell<RigidBody>> {
+        let ball = physics.add_body(RigidBody::new(
+            pos,
+            Quat::one(),
+            1.0,
+            sphereinertia_mass(0.5),
+        ));
+        physics.add_collider(
+            SphereCollider::new(&(ball.clone() as Rc<RefCell<dyn Body>>), 0.5)
+                .with_material(material),
+        );
+        ball
+    }
+
+    // Cube of side 1 and mass 1 at pos, turned by rot
+    fn cube(
+        physics: &mut Physics,
+        pos: Vec3,
+        rot: Quat,
+        material: Material,
+    ) -> Rc<RefCell<RigidBody>> {
+        let cube = physics.add_body(RigidBody::new(pos, rot, 1.0, cubeinertia_mass(1.0)));
+        physics.add_collider(
+            PolyhedraCollider::new(
+                &(cube.clone() as Rc<RefCell<dyn Body>>),
+                cube_polyhedra(1.0),
+            )
+            .with_material(material),
+        );
+        cube
+    }
+
+    #[test]
+    fn combine() {
+        let wood = Material::wood();
+        let pair = wood.combine(&Material::new(0.7, 0.6, 0.5));
+        assert!((pair.static_friction - 0.6).abs() < 1e-6);
+        assert!((pair.dynamic_friction - 0.5).abs() < 1e-6);
+        assert!((pair.restitution - 0.4).abs() < 1e-6);
+        assert!((pair.compliance - CONTACT_COMPLIANCE).abs() < 1e-12);
+
+        // The stronger rule wins, from either side
+        let ice = wood.combine(&Material::ice());
+        assert_eq!(ice, Material::ice().combine(&wood));
+        assert_eq!(ice.static_friction, 0.05);
+        assert_eq!(ice.restitution, 0.2);
+        let rubber = Material::ice().combine(&Material::rubber());
+        assert_eq!(rubber.friction_combine, CombineRule::Max);
+        assert_eq!(rubber.dynamic_friction, 0.8);
+        assert_eq!(rubber.restitution, 0.8);
+
+        let multiply = Material::new(0.5, 0.5, 0.5)
+            .with_combine(CombineRule::Multiply, CombineRule::Min)
+            .combine(&Material::new(0.4, 0.2, 0.1));
+        assert!((multiply.dynamic_friction - 0.1).abs() < 1e-6);
+        assert_eq!(multiply.restitution, 0.1);
+
+        // Softness adds up whatever the rules, damping is averaged
+        let soft = Material::rubber()
+            .with_compliance(1e-4, 2.0)
+            .combine(&Material::ice().with_compliance(1e-6, 0.0));
+        assert!((soft.compliance - 1.01e-4).abs() < 1e-10);
+        assert_eq!(soft.damping, 1.0);
+
+        // Rolling and torsional friction go by the friction rule
+        let rolling = Material::default()
+            .with_rolling_friction(0.1, 0.05)
+            .combine(&Material::rubber().with_rolling_friction(0.2, 0.0));
+        assert_eq!(rolling.rolling_friction, 0.2);
+        assert_eq!(rolling.torsional_friction, 0.05);
+    }
+
+    #[test]
+    fn damping() {
+        // How far a soft contact that sank 0.01 deeper this substep pushes the
+        // cube back out
+        let push = |damping: f32| {
+            let ground: Rc<RefCell<dyn Body>> = Rc::new(RefCell::new(RigidBody::new(
+                Vec3::zero(),
+                Quat::one(),
+                0.0,
+                zeroinertia_mass(),
+            )));
+            let cube = Rc::new(RefCell::new(RigidBody::new(
+                vec3(0.0, 1.0, 0.0),
+                Quat::one(),
+                1.0,
+                cubeinertia_mass(1.0),
+            )));
+            cube.borrow_mut().update_pos(vec3(0.0, -0.01, 0.0));
+            let mut contact = RColl::new(
+                [ground, cube.clone()],
+                vec![(vec3(0.0, 0.5, 0.0), vec3(0.0, 0.49, 0.0))],
+                vec3(0.0, 1.0, 0.0),
+                Material::default().with_compliance(1e-3, damping),
+            );
+            contact.iterate(1.0 / 60.0);
+            let y = cube.borrow().pos().y;
+            y - 0.99
+        };
+
+        // Damping works against the sinking on top of the spring
+        let (soft, damped) = (push(0.0), push(10.0));
+        assert!(soft > 0.0 && soft < 0.01);
+        assert!(damped > 1.2 * soft);
+    }
+
+    #[test]
+    fn slope() {
+        // How far a cube of the given material slides down a wooden slope
+        let slide = |material: Material| {
+            let angle = 20.0f32.to_radians();
+            let normal = vec3(-angle.sin(), angle.cos(), 0.0);
+            let mut physics = ground(normal, Material::wood());
+            let cube = cube(
+                &mut physics,
+                normal * 0.5,
+                Quat::from_angle_z(Rad(angle)),
+                material,
+            );
+
+            for _ in 0..60 {
+                physics.update(1.0 / 60.0);
+            }
+            let moved = cube.as_ref().borrow().pos() - normal * 0.5;
+            moved.magnitude()
+        };
+
+        assert!(slide(Material::wood()) < 1e-3);
+        assert!(slide(Material::rubber()) < 1e-3);
+        assert!(slide(Material::ice()) > 0.5);
+    }
+
+    #[test]
+    fn restitution_modes() {
+        let mode = |a: RestitutionMode, b: RestitutionMode| {
+            let contact = Material::default()
+                .with_restitution_mode(a)
+                .combine(&Material::default().with_restitution_mode(b));
+            assert_eq!(
+                contact,
+                Material::default()
+                    .with_restitution_mode(b)
+                    .combine(&Material::default().with_restitution_mode(a))
+            );
+            contact.restitution_mode
+        };
+        let (coefficient, elastic, inelastic) = (
+            RestitutionMode::Coefficient,
+            RestitutionMode::Elastic,
+            RestitutionMode::Inelastic,
+        );
+        assert_eq!(mode(coefficient, coefficient), coefficient);
+        assert_eq!(mode(coefficient, elastic), elastic);
+        assert_eq!(mode(coefficient, inelastic), inelastic);
+        assert_eq!(mode(elastic, inelastic), inelastic);
+
+        let rubber = Material::rubber();
+        assert_eq!(rubber.bounce(), 0.8);
+        assert_eq!(rubber.with_restitution_mode(elastic).bounce(), 1.0);
+        assert_eq!(rubber.with_restitution_mode(inelastic).bounce(), 0.0);
+    }
+
+    #[test]
+    fn bounce() {
+        // Highest a ball dropped from y = 2 gets after its first bounce
+        let bounce = |material: Material| {
+            let mut physics = ground(vec3(0.0, 1.0, 0.0), Material::default());
+            let ball = ball(&mut physics, vec3(0.0, 2.5, 0.0), material);
+
+            let mut bounced = false;
+            let mut highest: f32 = 0.0;
+            for _ in 0..120 {
+                physics.update(1.0 / 60.0);
+                let ball = ball.as_ref().borrow();
+                bounced |= ball.vel().y > 0.0;
+                if bounced {
+                    highest = highest.max(ball.pos().y - 0.5);
+                }
+            }
+            highest
+        };
+
+        let half = Material::new(1.0, 1.0, 0.5).with_combine(CombineRule::Min, CombineRule::Min);
+        assert!((bounce(half) - 0.5).abs() < 0.1);
+        let elastic = Material::default().with_restitution_mode(RestitutionMode::Elastic);
+        assert!(bounce(elastic) > 1.8);
+    }
+
+    #[test]
+    fn rolling_friction() {
+        // Speed and spin about the normal of a ball set rolling along x and
+        // spinning about y, three seconds later
+        let roll = |material: Material| {
+            let mut physics = ground(vec3(0.0, 1.0, 0.0), Material::default());
+            let ball = ball(&mut physics, vec3(0.0, 0.5, 0.0), material);
+            ball.borrow_mut().set_vel(vec3(2.0, 0.0, 0.0));
+            ball.borrow_mut().set_avel(vec3(0.0, 5.0, -4.0));
+
+            for _ in 0..180 {
+                physics.update(1.0 / 60.0);
+            }
+            let ball = ball.borrow();
+            (ball.vel().magnitude(), ball.avel().y.abs())
+        };
+
+        // Only the global rotation damping slows it down
+        let (vel, spin) = roll(Material::default());
+        assert!(vel > 1.5);
+        assert!(spin > 2.0);
+
+        let (vel, spin) = roll(Material::default().with_rolling_friction(0.1, 0.05));
+        assert!(vel < 0.05);
+        assert!(spin < 1e-3);
+    }
+}
@@ -81,20 +81,23 @@ impl ParticleFix {
     }
 }
 
-pub struct ParticleSimpleXWall {
+// Keeps a particle on the side normal.dot(x) >= offset
+pub struct ParticleHalfSpace {
     bodies: [Rc<RefCell<dyn Body>>; 1],
     lambda: f32,
     compliance: f32,
+    normal: Vec3,
+    offset: f32
 }
 
-impl Constraint for ParticleSimpleXWall {
+impl Constraint for ParticleHalfSpace {
     fn C(&self) -> f32 {
-        f32::max(-self.bodies()[0].borrow().pos().x, 0.0)
+        f32::max(self.offset - self.normal.dot(self.bodies()[0].borrow().pos()), 0.0)
     }
     fn dC(&self) -> Vec<Vec3> {
         let mut g = Vec3::zero();
-        if self.bodies()[0].borrow().pos().x < 0.0 {
-            g.x = -1.0;
+        if self.normal.dot(self.bodies()[0].borrow().pos()) < self.offset {
+            g = -self.normal;
         }
         vec!(g)
     }
@@ -102,12 +105,14 @@ impl Constraint for ParticleSimpleXWall {
     constraint_getset!(1);
 }
 
-impl ParticleSimpleXWall {
-    pub fn new(bodies: [Rc<RefCell<dyn Body>>; 1], compliance: f32) -> Self {
+impl ParticleHalfSpace {
+    pub fn new(bodies: [Rc<RefCell<dyn Body>>; 1], normal: Vec3, offset: f32, compliance: f32) -> Self {
         Self {
             bodies,
             lambda: 0.0,
-            compliance
+            compliance,
+            normal: normal.normalize(),
+            offset
         }
     }
 }
@@ -1,9 +1,12 @@
 use crate::body::*;
-use crate::collision;
-use crate::collision::clipping;
-use crate::collision::clipping::*;
+use crate::collision::aabb::*;
+use crate::collision::broadphase::*;
+use crate::collision::bvh::*;
+use crate::collision::cache::*;
 use crate::collision::collider::*;
-use crate::collision::gjk::*;
+use crate::collision::contact::*;
+use crate::collision::raycast::*;
+use crate::collision::toi::*;
 use crate::config::*;
 use crate::constraint::Constraint;
 use crate::particle::*;
@@ -13,7 +16,6 @@ use crate::rigidbody_constraint::RColl;
 use crate::rigidbody_constraint::RDist;
 use crate::timestep_schedule::*;
 use ::core::f32;
-use itertools::Itertools;
 use std::borrow::Borrow;
 use std::borrow::BorrowMut;
 use std::cell::RefCell;
@@ -24,11 +26,20 @@ use three_d::*;
 
 type BodyRc = Rc<RefCell<dyn Body>>;
 
+fn same_body(a: &BodyRc, b: &BodyRc) -> bool {
+    std::ptr::addr_eq(Rc::as_ptr(a), Rc::as_ptr(b))
+}
+
 pub struct Physics {
     bodies: Vec<Rc<RefCell<dyn Body>>>,
     colliders: Vec<Box<dyn Collider>>,
     constraint: Vec<Box<dyn Constraint>>,
     temp_constraint: Vec<Box<dyn Constraint>>,
+    // Contact constraints of the current substep, by collider pair
+    contacts: Vec<((usize, usize), RColl)>,
+    contact_cache: ContactCache,
+    // Whether contacts pick up where the same contacts ended last step
+    warm_start: bool,
 
     gravity: Vec3,
 
@@ -36,6 +47,7 @@ pub struct Physics {
     iterations: usize,
 
     scheduler: Box<dyn TimestepScheduler>,
+    broadphase: Box<dyn Broadphase>,
 }
 
 impl Physics {
@@ -45,6 +57,9 @@ impl Physics {
             colliders: Vec::new(),
             constraint: Vec::new(),
             temp_constraint: Vec::new(),
+            contacts: Vec::new(),
+            contact_cache: ContactCache::new(),
+            warm_start: true,
 
             gravity,
 
@@ -52,9 +67,21 @@ impl Physics {
             iterations,
 
             scheduler: Box::new(UniformSchedule::new(substeps)),
+            broadphase: Box::new(DynamicTree::new(BROADPHASE_MARGIN)),
+        }
+    }
+
+    pub fn set_broadphase<T: Broadphase + 'static>(&mut self, broadphase: T) {
+        self.broadphase = Box::new(broadphase);
+        for (i, collider) in self.colliders.iter().enumerate() {
+            self.broadphase.insert(i, collider.aabb());
         }
     }
 
+    pub fn set_warm_start(&mut self, warm_start: bool) {
+        self.warm_start = warm_start;
+    }
+
     pub fn add_body<T: Body + 'static>(&mut self, body: T) -> Rc<RefCell<T>> {
         let particle = Rc::new(RefCell::new(body));
 
@@ -63,6 +90,8 @@ impl Physics {
         particle.clone()
     }
     pub fn add_collider<T: Collider + 'static>(&mut self, collider: T) {
+        self.broadphase
+            .insert(self.colliders.len(), collider.aabb());
         self.colliders.push(Box::new(collider) as Box<dyn Collider>);
     }
     pub fn add_constraint<T: Constraint + 'static>(&mut self, constraint: T) {
@@ -73,16 +102,23 @@ impl Physics {
     }
 
     pub fn update(&mut self, dt: f32) {
-        let mut potential_collisions = Vec::<(usize, usize)>::new();
-        for pair in self.colliders.iter().enumerate().combinations(2) {
-            let (i, a) = pair[0];
-            let (j, b) = pair[1];
-
-            if true {
-                potential_collisions.push((i, j));
-            }
+        // Bounds cover the motion over the whole frame, since the pairs are
+        // only found once and reused for every substep.
+        for (i, collider) in self.colliders.iter().enumerate() {
+            self.broadphase.update(i, collider.swept_aabb(dt));
         }
-        let collision_pairs = potential_collisions;
+        // Parts of a compound body never collide with each other
+        let collision_pairs: Vec<(usize, usize)> = self
+            .broadphase
+            .pairs()
+            .into_iter()
+            .filter(|(i, j)| {
+                !same_body(
+                    &self.colliders[*i].get_body(),
+                    &self.colliders[*j].get_body(),
+                )
+            })
+            .collect();
 
         for substep in 0..self.substeps {
             let dt = self.scheduler.get(substep, dt);
@@ -95,174 +131,33 @@ impl Physics {
                 body.as_ref().borrow_mut().predict(dt);
             }
 
-            for (pair_index, (i, j)) in collision_pairs.iter().enumerate() {
+            let mut pairs = collision_pairs.clone();
+            for pair in self.continuous_collisions() {
+                if !pairs.contains(&pair) {
+                    pairs.push(pair);
+                }
+            }
+
+            for (i, j) in pairs.iter() {
                 let a = &self.colliders[*i];
                 let b = &self.colliders[*j];
 
-                let result = gjk(a, b, true);
-                if let Some(simpl) = result {
-                    let (normal, depth, va, vb) = epa(a, b, simpl);
-                    // println!("{:?}", normal);
-                    // let depth = depth.abs();
-
-                    if normal.magnitude2().is_nan() || depth < 0.0 {
-                        continue;
-                    }
-
-                    let a_vert = a.support(normal);
-                    let a_vertices = a.get_vertices().unwrap();
-                    let a_faces = a.get_faces().unwrap();
-                    let a_ind = a_vertices
-                        .iter()
-                        .position(|v| v.distance2(a_vert.to_vec()) < f32::EPSILON * f32::EPSILON)
-                        .unwrap();
-
-                    let b_vert = b.support(-normal);
-                    let b_vertices = b.get_vertices().unwrap();
-                    let b_faces = b.get_faces().unwrap();
-                    let b_ind = b_vertices
-                        .iter()
-                        .position(|v| v.distance2(b_vert.to_vec()) < f32::EPSILON * f32::EPSILON)
-                        .unwrap();
-
-                    let mut max_dot = -f32::INFINITY;
-                    let mut a_ref: &Vec<usize> = &a_faces[0];
-                    for face in a_faces {
-                        if face.contains(&a_ind) {
-                            let dot = ccw_normal(&face, &a_vertices).dot(-normal);
-                            if max_dot < dot {
-                                a_ref = face;
-                                max_dot = dot;
-                            }
-                        }
-                    }
-
-                    max_dot = -f32::INFINITY;
-                    let mut b_ref: &Vec<usize> = &b_faces[0];
-                    for face in b_faces {
-                        if face.contains(&b_ind) {
-                            let dot = ccw_normal(&face, &b_vertices).dot(normal);
-                            if max_dot < dot {
-                                b_ref = face;
-                                max_dot = dot;
-                            }
-                        }
-                    }
-
-                    let a_ref = a_ref;
-                    let b_ref = b_ref;
-
-                    let a_face: Vec<Vec3> = a_ref.iter().map(|i| a_vertices[*i]).collect();
-                    let b_face: Vec<Vec3> = b_ref.iter().map(|i| b_vertices[*i]).collect();
-
-                    let a_normal = (a_face[1] - a_face[0]).cross(a_face[2] - a_face[0]);
-                    let b_normal = (b_face[1] - b_face[0]).cross(b_face[2] - b_face[0]);
-
-                    let inc_face;
-                    let ref_face;
-                    let mut adj_face_normals = Vec::new();
-
-                    let mut a_is_ref;
-                    if a_normal.normalize().dot(normal) < b_normal.normalize().dot(-normal) {
-                        inc_face = b_face;
-                        ref_face = a_face;
-                        a_is_ref = true;
-
-                        for (edge_a, edge_b) in
-                            a_ref.iter().chain([a_ref[0]].iter()).tuple_windows()
-                        {
-                            for face in a_faces {
-                                if face == a_ref {
-                                    continue;
-                                }
-                                if face.contains(edge_a) && face.contains(edge_b) {
-                                    adj_face_normals
-                                        .push((a_vertices[*edge_a], ccw_normal(face, &a_vertices)))
-                                }
-                            }
-                        }
-                    } else {
-                        inc_face = a_face;
-                        ref_face = b_face;
-                        a_is_ref = false;
-
-                        for (edge_a, edge_b) in
-                            b_ref.iter().chain([b_ref[0]].iter()).tuple_windows()
-                        {
-                            for face in b_faces {
-                                if face == b_ref {
-                                    continue;
-                                }
-                                if face.contains(edge_a) && face.contains(edge_b) {
-                                    adj_face_normals
-                                        .push((b_vertices[*edge_a], ccw_normal(face, &b_vertices)))
-                                }
-                            }
-                        }
-                    }
-                    let adj_face_normals = adj_face_normals;
-
-                    let mut result = inc_face.clone();
-                    // println!("-----");
-                    // println!("{:?}", (ref_face[1] - ref_face[0]).cross(ref_face[2] - ref_face[0]));
-                    // println!("{}", result.len());
-                    for (origin, clip_normal) in adj_face_normals {
-                        // println!("{:?} {:?}", origin, clip_normal);
-                        result = clip(&result, &origin, &(clip_normal), true);
-                        // println!("{}", result.len());
-                    }
-                    result = clip(
-                        &result,
-                        &ref_face[0],
-                        &(-(ref_face[0] - ref_face[1]).cross(ref_face[2] - ref_face[0])),
-                        false,
+                let material = a.material().combine(&b.material());
+                for manifold in generate_manifolds(a.as_ref(), b.as_ref()) {
+                    let mut contact = RColl::new(
+                        [a.get_body(), b.get_body()],
+                        manifold.contacts,
+                        manifold.normal,
+                        material,
+                    )
+                    .with_features(manifold.features)
+                    .with_restitution_threshold(
+                        RESTITUTION_THRESHOLD * self.gravity.magnitude() * dt,
                     );
-
-                    let mut contact_list = Vec::new();
-                    let mut depth_list = Vec::new();
-                    for contact_inc in result {
-                        // Project contact to incident face
-
-                        let closest_point = ref_face
-                            .iter()
-                            .fold((-f32::INFINITY, Vec3::zero()), |(max, point_max), v| {
-                                let value = v.distance2(contact_inc);
-                                if max > value {
-                                    (max, point_max)
-                                } else {
-                                    (value, *v)
-                                }
-                            })
-                            .1;
-                        let point_diff = contact_inc - closest_point;
-                        let contact_peneration;
-
-                        let contacts;
-                        if !a_is_ref {
-                            contact_peneration = point_diff.dot(normal);
-                            contacts = (contact_inc - contact_peneration * normal, contact_inc);
-                        } else {
-                            contact_peneration = point_diff.dot(-normal);
-                            contacts = (contact_inc, contact_inc + contact_peneration * normal);
-                        }
-                        println!("{}", contact_peneration);
-                        contact_list.push(contacts);
-                        depth_list.push(contact_peneration);
+                    if self.warm_start {
+                        contact.warm_start(&self.contact_cache, (*i, *j));
                     }
-
-                    self.temp_constraint.push(Box::new(RColl::new(
-                        [a.get_body(), b.get_body()],
-                        // [a.get_body().as_ref().borrow().apos().rotate_vector(*va), b.get_body().as_ref().borrow().apos().rotate_vector(*va)],
-                        // [a.get_body().as_ref().borrow().pos_at(va), b.get_body().as_ref().borrow().pos_at(vb)],
-                        contact_list,
-                        // [Vec3::zero(), Vec3::zero()],
-                        // collision_normals[pair_index].unwrap(),
-                        normal,
-                        depth_list,
-                        // actual_normal,
-                        // actual_depth,
-                        0.0000001,
-                    )))
+                    self.contacts.push(((*i, *j), contact));
                 }
             }
 
@@ -274,6 +169,9 @@ impl Physics {
             }
 
             for _ in 0..self.iterations {
+                for (_, contact) in &mut self.contacts {
+                    contact.iterate(dt / (self.iterations as f32));
+                }
                 for constraint in &mut self.temp_constraint {
                     constraint.iterate(dt / (self.iterations as f32));
                     // println!("{:?}", self.bodies[2].as_ref().borrow().apos());
@@ -297,6 +195,9 @@ impl Physics {
             }
 
             // Velocity update
+            for (_, contact) in &mut self.contacts {
+                contact.velocity_update(dt);
+            }
             for constraint in &mut self.temp_constraint {
                 constraint.velocity_update(dt);
             }
@@ -307,10 +208,274 @@ impl Physics {
             // let pos = self.bodies[3].as_ref().borrow().pos();
             // println!("{} {} {}", pos.x, pos.y, pos.z);
             self.temp_constraint.clear();
+
+            // Only contacts that still exist are kept for the next substep
+            self.contact_cache.clear();
+            for (pair, contact) in self.contacts.drain(..) {
+                contact.store(&mut self.contact_cache, pair);
+            }
         }
     }
 
+    // Sweeps every body flagged for CCD from where it was before predict to
+    // where it is now. A body that hits something on the way is stopped just
+    // past the time of impact, so that the discrete contacts catch it, and
+    // the colliders it hit are returned. Everything else is treated as
+    // standing still at its predicted pose.
+    fn continuous_collisions(&self) -> Vec<(usize, usize)> {
+        let mut pairs = Vec::new();
+
+        for body in &self.bodies {
+            let (pos_prev, apos_prev, pos, apos) = {
+                let body = body.as_ref().borrow();
+                if !body.ccd() || body.invmass() == 0.0 {
+                    continue;
+                }
+                (body.pos_prev(), body.apos_prev(), body.pos(), body.apos())
+            };
+            let sweep = Sweep::between(pos_prev, apos_prev, pos, apos);
+
+            // Back to the start of the sweep
+            body.as_ref().borrow_mut().update_pos(pos_prev - pos);
+            body.as_ref()
+                .borrow_mut()
+                .update_apos(apos_prev * apos.invert());
+
+            let mut first: Option<(TimeOfImpact, usize, usize)> = None;
+            for (i, collider) in self.colliders.iter().enumerate() {
+                if !same_body(&collider.get_body(), body) {
+                    continue;
+                }
+                for j in self.broadphase.query_aabb(&sweep.bounds(collider.as_ref())) {
+                    let other = &self.colliders[j];
+                    if same_body(&other.get_body(), body) {
+                        continue;
+                    }
+                    let Some(hit) =
+                        time_of_impact(collider.as_ref(), &sweep, other.as_ref(), &Sweep::zero())
+                    else {
+                        continue;
+                    };
+                    // Already touching at the start is left to the discrete contacts
+                    if hit.t > 0.0 && first.as_ref().is_none_or(|(f, ..)| hit.t < f.t) {
+                        first = Some((hit, i, j));
+                    }
+                }
+            }
+
+            let mut body = body.as_ref().borrow_mut();
+            match first {
+                Some((hit, i, j)) => {
+                    // Never past where the body would have got to without CCD,
+                    // so slow bodies settling on something are left alone
+                    let distance = sweep.translation.magnitude();
+                    let t = if distance > f32::EPSILON {
+                        (hit.t + CCD_SLOP / distance).min(1.0)
+                    } else {
+                        1.0
+                    };
+                    body.update_pos(sweep.translation * t);
+                    body.update_apos(sweep.rotation_at(t));
+                    pairs.push((i.min(j), i.max(j)));
+                }
+                None => {
+                    body.update_pos(sweep.translation);
+                    body.update_apos(apos * apos_prev.invert());
+                }
+            }
+        }
+
+        pairs
+    }
+
     pub fn bodies(&self) -> Vec<Rc<RefCell<dyn Body>>> {
         self.bodies.clone()
     }
+
+    // Bodies owning a collider whose bounding box overlaps `aabb`.
+    pub fn query_aabb(&self, aabb: &Aabb) -> Vec<BodyRc> {
+        self.broadphase
+            .query_aabb(aabb)
+            .into_iter()
+            .filter(|i| self.colliders[*i].aabb().overlaps(aabb))
+            .map(|i| self.colliders[i].get_body())
+            .collect()
+    }
+
+    pub fn query_sphere(&self, center: Vec3, radius: f32) -> Vec<BodyRc> {
+        let bounds = Aabb::new(center, center).expand(radius);
+        self.broadphase
+            .query_aabb(&bounds)
+            .into_iter()
+            .filter(|i| self.colliders[*i].aabb().distance2(center) <= radius * radius)
+            .map(|i| self.colliders[i].get_body())
+            .collect()
+    }
+
+    // Bodies owning a collider whose bounding box is hit by the ray, nearest first.
+    pub fn query_ray(&self, origin: Vec3, dir: Vec3, max_t: f32) -> Vec<BodyRc> {
+        let mut hits: Vec<(f32, usize)> = self
+            .broadphase
+            .query_ray(origin, dir, max_t)
+            .into_iter()
+            .filter_map(|i| {
+                self.colliders[i]
+                    .aabb()
+                    .ray_hit(origin, dir, max_t)
+                    .map(|t| (t, i))
+            })
+            .collect();
+        hits.sort_by(|a, b| a.0.total_cmp(&b.0));
+
+        hits.into_iter()
+            .map(|(_, i)| self.colliders[i].get_body())
+            .collect()
+    }
+
+    // Closest collider hit by the ray, with the body owning it. Broadphase
+    // bounds cover the last frame's motion, so candidates are checked against
+    // where their colliders are now before the exact cast.
+    pub fn cast_ray(&self, origin: Vec3, dir: Vec3, max_t: f32) -> Option<(BodyRc, RayHit)> {
+        self.broadphase
+            .query_ray(origin, dir, max_t)
+            .into_iter()
+            .filter(|i| {
+                self.colliders[*i]
+                    .aabb()
+                    .ray_hit(origin, dir, max_t)
+                    .is_some()
+            })
+            .filter_map(|i| {
+                self.colliders[i]
+                    .cast_ray(origin, dir, max_t)
+                    .map(|hit| (i, hit))
+            })
+            .min_by(|a, b| a.1.t.total_cmp(&b.1.t))
+            .map(|(i, hit)| (self.colliders[i].get_body(), hit))
+    }
+
+    // First collider hit by `collider` moving along `sweep`, with the body
+    // owning it. Colliders sharing the body of the one being cast are skipped.
+    pub fn cast_shape(
+        &self,
+        collider: &dyn Collider,
+        sweep: &Sweep,
+    ) -> Option<(BodyRc, TimeOfImpact)> {
+        let body = collider.get_body();
+        let bounds = sweep.bounds(collider);
+        self.broadphase
+            .query_aabb(&bounds)
+            .into_iter()
+            .filter(|i| self.colliders[*i].aabb().overlaps(&bounds))
+            .filter(|i| !same_body(&self.colliders[*i].get_body(), &body))
+            .filter_map(|i| {
+                time_of_impact(collider, sweep, self.colliders[i].as_ref(), &Sweep::zero())
+                    .map(|hit| (i, hit))
+            })
+            .min_by(|a, b| a.1.t.total_cmp(&b.1.t))
+            .map(|(i, hit)| (self.colliders[i].get_body(), hit))
+    }
+}
+
+#[cfg(test)]
+mod physics_test {
+    use super::*;
+    use crate::collision::chull::*;
+    use crate::cube::*;
+    use crate::inertiatensor::*;
+
+    // Balls of radius 0.5 resting at the given x
+    fn balls(xs: &[f32]) -> Physics {
+        let mut physics = Physics::new(vec3(0.0, -9.8, 0.0), SUBS, ITER);
+        for x in xs {
+            let ball = physics.add_body(RigidBody::new(
+                vec3(*x, 0.0, 0.0),
+                Quat::one(),
+                1.0,
+                sphereinertia_mass(0.5),
+            ));
+            physics.add_collider(SphereCollider::new(&(ball as BodyRc), 0.5));
+        }
+        physics
+    }
+
+    #[test]
+    fn warm_start() {
+        // How far the top of a stack of three staggered cubes wanders over two
+        // seconds, once it has landed
+        let drift = |warm_start: bool| {
+            let mut physics = Physics::new(vec3(0.0, -9.8, 0.0), SUBS, ITER);
+            physics.set_warm_start(warm_start);
+            physics.add_collider(PlaneCollider::new(vec3(0.0, 1.0, 0.0), 0.0));
+            let mut top = None;
+            for i in 0..3 {
+                let cube = physics.add_body(RigidBody::new(
+                    vec3(0.05 * i as f32, 0.5 + i as f32, 0.0),
+                    Quat::one(),
+                    1.0,
+                    cubeinertia_mass(1.0),
+                ));
+                physics.add_collider(PolyhedraCollider::new(
+                    &(cube.clone() as BodyRc),
+                    cube_polyhedra(1.0),
+                ));
+                top = Some(cube);
+            }
+            let top = top.unwrap();
+
+            for _ in 0..60 {
+                physics.update(1.0 / 60.0);
+            }
+            let landed = top.as_ref().borrow().pos();
+            for _ in 0..120 {
+                physics.update(1.0 / 60.0);
+            }
+            let moved = top.as_ref().borrow().pos() - landed;
+            moved.magnitude()
+        };
+
+        // Contacts that keep their friction anchors hold the stack in place
+        let (warm, cold) = (drift(true), drift(false));
+        assert!(warm < 0.5 * cold);
+    }
+
+    #[test]
+    fn broadphases_agree() {
+        let mut physics = balls(&[0.0, 0.9, 1.8, 5.0, 5.8, 10.0]);
+        let mut tree = physics.broadphase.pairs();
+        tree.sort();
+        assert_eq!(tree, vec![(0, 1), (1, 2), (3, 4)]);
+
+        physics.set_broadphase(SweepAndPrune::new(BROADPHASE_MARGIN));
+        let mut sap = physics.broadphase.pairs();
+        sap.sort();
+        assert_eq!(sap, tree);
+    }
+
+    #[test]
+    fn world_queries() {
+        let mut physics = balls(&[0.0, 2.0, 4.0, 6.0]);
+        for sap in [false, true] {
+            if sap {
+                physics.set_broadphase(SweepAndPrune::new(BROADPHASE_MARGIN));
+            }
+            let xs = |bodies: Vec<BodyRc>| -> Vec<f32> {
+                bodies.iter().map(|b| b.as_ref().borrow().pos().x).collect()
+            };
+
+            let aabb = Aabb::new(vec3(1.0, -1.0, -1.0), vec3(3.6, 1.0, 1.0));
+            let mut hits = xs(physics.query_aabb(&aabb));
+            hits.sort_by(f32::total_cmp);
+            assert_eq!(hits, vec![2.0, 4.0]);
+
+            // Reaches into the boxes of the balls on either side
+            let mut hits = xs(physics.query_sphere(vec3(5.0, 0.0, 0.0), 0.6));
+            hits.sort_by(f32::total_cmp);
+            assert_eq!(hits, vec![4.0, 6.0]);
+            assert!(physics.query_sphere(vec3(5.0, 0.0, 0.0), 0.4).is_empty());
+
+            let hits = xs(physics.query_ray(vec3(10.0, 0.0, 0.0), -Vec3::unit_x(), 7.0));
+            assert_eq!(hits, vec![6.0, 4.0]);
+        }
+    }
 }
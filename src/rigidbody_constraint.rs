@@ -1,6 +1,9 @@
 use crate::body::*;
+use crate::collision::cache::*;
+use crate::collision::contact::ContactFeature;
 use crate::config::*;
 use crate::constraint::*;
+use crate::material::Material;
 use std::cell::RefCell;
 use std::iter::zip;
 use std::rc::Rc;
@@ -77,7 +80,7 @@ impl Constraint for RDist {
             result.push(
                 0.5 * Quat::from_sv(
                     0.0,
-                    self.bodies()[i].as_ref().borrow().invinertia()
+                    self.bodies()[i].as_ref().borrow().invinertia_world()
                         * self.true_offsets()[i].cross(self.dC()[i])
                         * dlambda,
                 ) * self.bodies()[i].as_ref().borrow().apos(),
@@ -98,7 +101,7 @@ impl Constraint for RDist {
         for i in 0..self.len() {
             sum += self.bodies()[i].as_ref().borrow().invmass()
                 + self.true_offsets()[i].cross(dC[0]).dot(
-                    self.bodies()[i].as_ref().borrow().invinertia()
+                    self.bodies()[i].as_ref().borrow().invinertia_world()
                         * self.true_offsets()[i].cross(dC[0]),
                 );
         }
@@ -110,11 +113,24 @@ impl Constraint for RDist {
 pub struct RColl {
     bodies: [Rc<RefCell<dyn Body>>; 2],
     original_velocity: Vec<Vec3>,
-    contacts: Vec<(Vec3, Vec3)>,
+    // Contact points in the local frames of the two bodies
+    points: Vec<(Vec3, Vec3)>,
     normal: Vec3,
-    depth: Vec<f32>,
+    // Accumulated normal lambda of each contact, never negative
     lambda: Vec<f32>,
-    compliance: f32,
+    // Part of lambda carried over from the last step, which pushes nothing
+    // this substep
+    warm: Vec<f32>,
+    // Combined material of the two colliders
+    material: Material,
+    features: Vec<ContactFeature>,
+    // Body-local points static friction pins together, where each contact
+    // last started to stick
+    anchors: Vec<(Vec3, Vec3)>,
+    // Accumulated tangential lambda of each contact
+    lambda_t: Vec<f32>,
+    // Approach speed below which the contact doesn't bounce
+    restitution_threshold: f32,
 }
 
 impl RColl {
@@ -122,118 +138,229 @@ impl RColl {
         bodies: [Rc<RefCell<dyn Body>>; 2],
         contacts: Vec<(Vec3, Vec3)>,
         normal: Vec3,
-        depth: Vec<f32>,
-        compliance: f32,
+        material: Material,
     ) -> Self {
         let len = contacts.len();
+        let points: Vec<(Vec3, Vec3)> = contacts
+            .iter()
+            .map(|(a, b)| {
+                (
+                    bodies[0].as_ref().borrow().to_local(*a),
+                    bodies[1].as_ref().borrow().to_local(*b),
+                )
+            })
+            .collect();
         let mut result = Self {
             bodies,
             original_velocity: vec![Vec3::zero(); len],
-            contacts, // global coordinate
+            anchors: points.clone(),
+            points,
             normal,
-            depth,
             lambda: vec![0.0; len],
-            compliance,
+            warm: vec![0.0; len],
+            material,
+            features: (0..len).map(|i| i as ContactFeature).collect(),
+            lambda_t: vec![0.0; len],
+            restitution_threshold: 0.0,
         };
 
-        let r = result.r();
-
-        for i in 0..result.contacts.len() {
-            result.original_velocity[i] = result.bodies[0].as_ref().borrow().vel()
-                + result.bodies[0].as_ref().borrow().avel().cross(r[i].0)
-                - (result.bodies[1].as_ref().borrow().vel()
-                    + result.bodies[1].as_ref().borrow().avel().cross(r[i].1));
-        }
+        result.original_velocity = result
+            .r()
+            .into_iter()
+            .map(|r| result.relative_velocity(r))
+            .collect();
 
         result
     }
 
-    fn r(&self) -> Vec<(Vec3, Vec3)> {
-        (0..self.contacts.len())
-            .map(|i| {
-                (
-                    self.bodies[0].as_ref().borrow().apos().rotate_vector(
-                        self.bodies[0]
-                            .as_ref()
-                            .borrow()
-                            .to_local(self.contacts[i].0),
-                    ),
-                    self.bodies[1].as_ref().borrow().apos().rotate_vector(
-                        self.bodies[1]
-                            .as_ref()
-                            .borrow()
-                            .to_local(self.contacts[i].1),
-                    ),
-                )
-            })
-            .collect()
+    pub fn with_features(mut self, features: Vec<ContactFeature>) -> Self {
+        self.features = features;
+        self
     }
 
-    fn dq_vec(&self, dlambda: f32, i: usize) -> (Quat, Quat) {
-        let mut result = vec![Quat::zero(), Quat::zero()];
-        // println!("{:?}", r.0.cross(self.dC()[0] * dlambda));
-        let r = self.r();
+    pub fn with_restitution_threshold(mut self, threshold: f32) -> Self {
+        self.restitution_threshold = threshold;
+        self
+    }
+
+    // Picks up the contacts that already existed last step: they start from
+    // part of their lambda. Nothing is moved, the correction that lambda
+    // stands for has already been made.
+    pub fn warm_start(&mut self, cache: &ContactCache, pair: (usize, usize)) {
+        for i in 0..self.points.len() {
+            if let Some(cached) = cache.get(pair, self.features[i]) {
+                self.anchors[i] = cached.anchors;
+                self.warm[i] = cached.lambda.max(0.0) * WARM_START;
+                self.lambda[i] = self.warm[i];
+            }
+        }
+    }
+
+    pub fn store(&self, cache: &mut ContactCache, pair: (usize, usize)) {
+        for i in 0..self.points.len() {
+            cache.insert(
+                pair,
+                self.features[i],
+                CachedContact {
+                    anchors: self.anchors[i],
+                    lambda: self.lambda[i],
+                },
+            );
+        }
+    }
+
+    // Current world positions of the contact points
+    fn world_points(&self, i: usize) -> (Vec3, Vec3) {
         (
-            0.5 * Quat::from_sv(
-                0.0,
-                self.bodies()[0].as_ref().borrow().invinertia()
-                    * r[i].0.cross(self.dC()[0] * dlambda),
-            ) * self.bodies()[0].as_ref().borrow().apos(),
-            0.5 * Quat::from_sv(
-                0.0,
-                self.bodies()[1].as_ref().borrow().invinertia()
-                    * r[i].1.cross(self.dC()[1] * dlambda),
-            ) * self.bodies()[1].as_ref().borrow().apos(),
+            self.bodies[0].as_ref().borrow().to_global(self.points[i].0),
+            self.bodies[1].as_ref().borrow().to_global(self.points[i].1),
         )
+    }
 
-        // result[0] = result[0].invert();
-        // result[1] = result[1].invert();
-        // println!("{} {} {} {} / {} {} {} {}", result[0].v.x, result[0].v.y, result[0].v.z, result[0].s, result[1].v.x, result[1].v.y, result[1].v.z, result[1].s);
-        // vec![Quat::zero(), Quat::zero()]
+    // How far the contact points have passed each other along the normal
+    pub fn penetration(&self, i: usize) -> f32 {
+        let (a, b) = self.world_points(i);
+        (a - b).dot(self.normal)
     }
 
-    fn invmass_sum_vec(&self) -> Vec<f32> {
-        let r = self.r();
+    // Offsets of body-local points from the body centers, in world axes
+    fn lever(&self, local: (Vec3, Vec3)) -> (Vec3, Vec3) {
+        (
+            self.bodies[0].as_ref().borrow().apos().rotate_vector(local.0),
+            self.bodies[1].as_ref().borrow().apos().rotate_vector(local.1),
+        )
+    }
 
-        (0..self.contacts.len())
-            .map(|i| {
-                let mut sum: f32 = 0.0;
-                sum += self.bodies()[0].as_ref().borrow().invmass();
-                sum += self.bodies()[1].as_ref().borrow().invmass();
+    // Penetration with both bodies back where they started the substep
+    fn previous_penetration(&self, i: usize) -> f32 {
+        let [a, b] = [0, 1].map(|k| self.bodies[k].as_ref().borrow());
+        let a = a.pos_prev() + a.apos_prev().rotate_vector(self.points[i].0);
+        let b = b.pos_prev() + b.apos_prev().rotate_vector(self.points[i].1);
+        (a - b).dot(self.normal)
+    }
 
-                sum += r[i].0.cross(self.normal).dot(
-                    self.bodies()[0].as_ref().borrow().invinertia() * r[i].0.cross(self.normal),
-                );
+    fn r(&self) -> Vec<(Vec3, Vec3)> {
+        self.points.iter().map(|p| self.lever(*p)).collect()
+    }
 
-                sum += r[i].1.cross(self.normal).dot(
-                    self.bodies()[1].as_ref().borrow().invinertia() * r[i].1.cross(self.normal),
-                );
+    // How far the anchors of contact i have slid apart along the contact plane
+    fn slip(&self, i: usize) -> Vec3 {
+        let a = self.bodies[0].as_ref().borrow().to_global(self.anchors[i].0);
+        let b = self.bodies[1].as_ref().borrow().to_global(self.anchors[i].1);
+        let d = a - b;
+        d - self.normal * d.dot(self.normal)
+    }
 
-                sum
+    // Velocity of a's contact point relative to b's
+    fn relative_velocity(&self, r: (Vec3, Vec3)) -> Vec3 {
+        let a = self.bodies[0].as_ref().borrow();
+        let b = self.bodies[1].as_ref().borrow();
+        a.vel() + a.avel().cross(r.0) - (b.vel() + b.avel().cross(r.1))
+    }
+
+    // Generalized inverse mass at offsets r along the direction n
+    fn invmass_sum(&self, r: (Vec3, Vec3), n: Vec3) -> f32 {
+        [r.0, r.1]
+            .iter()
+            .zip(self.bodies.iter())
+            .map(|(r, body)| {
+                let body = body.as_ref().borrow();
+                body.invmass() + r.cross(n).dot(body.invinertia_world() * r.cross(n))
             })
-            .collect()
+            .sum()
     }
 
-    fn C_vec(&self) -> &Vec<f32> {
-        &self.depth
+    // Positional impulse p at offsets r, pushing b along p and a against it
+    fn apply(&self, r: (Vec3, Vec3), p: Vec3) {
+        for (k, (r, p)) in [(r.0, -p), (r.1, p)].into_iter().enumerate() {
+            let mut body = self.bodies[k].as_ref().borrow_mut();
+            let invmass = body.invmass();
+            body.update_pos(invmass * p);
+
+            let dq = 0.5
+                * Quat::from_sv(0.0, body.invinertia_world() * r.cross(p))
+                * body.apos();
+            if dq.magnitude2() > f32::EPSILON * f32::EPSILON {
+                body.add_apos(dq);
+            }
+        }
     }
 
-    fn dlambda_vec(&self, dt: f32) -> Vec<f32> {
-        (0..self.contacts.len())
-            .map(|i| {
-                let alpha = self.compliance() / (dt * dt);
-                let C = self.C_vec()[i];
+    // Impulse changing the relative velocity at offsets r by dv, split between
+    // the given number of contacts
+    fn apply_velocity(&self, r: (Vec3, Vec3), dv: Vec3, share: f32) {
+        if dv.magnitude2() < f32::EPSILON * f32::EPSILON {
+            return;
+        }
+        let p = dv / self.invmass_sum(r, dv.normalize()) / share;
+
+        for (k, (r, p)) in [(r.0, p), (r.1, -p)].into_iter().enumerate() {
+            let mut body = self.bodies[k].as_ref().borrow_mut();
+            let new_vel = body.vel() + p * body.invmass();
+            body.set_vel(new_vel);
+            let new_avel = body.avel() + body.invinertia_world() * r.cross(p);
+            body.set_avel(new_avel);
+        }
+    }
+
+    // Slows the relative spin w of a against b with an angular impulse of at
+    // most coefficient * load / dt
+    fn resist_spin(&self, w: Vec3, coefficient: f32, dt: f32) {
+        let w_abs = w.magnitude();
+        if coefficient <= 0.0 || w_abs < f32::EPSILON {
+            return;
+        }
+        let axis = w / w_abs;
+        let invinertia_sum: f32 = self
+            .bodies
+            .iter()
+            .map(|body| axis.dot(body.as_ref().borrow().invinertia_world() * axis))
+            .sum();
+        if invinertia_sum < f32::EPSILON {
+            return;
+        }
+        let load: f32 = (0..self.points.len()).map(|i| self.load(i)).sum();
+        let max_l = coefficient * load / dt;
+        let l = -axis * f32::min(max_l, w_abs / invinertia_sum);
+
+        for (k, l) in [l, -l].into_iter().enumerate() {
+            let mut body = self.bodies[k].as_ref().borrow_mut();
+            let new_avel = body.avel() + body.invinertia_world() * l;
+            body.set_avel(new_avel);
+        }
+    }
 
-                let dot = self.invmass_sum_vec()[i];
-                let denom = dot + alpha;
+    // Static friction: pulls the anchors of contact i back together along the
+    // contact plane, by the given share of the slip, if that stays within its
+    // own friction cone. Otherwise the contact slides: it is left to dynamic
+    // friction in the velocity pass, and its anchors start over from here.
+    fn stick(&mut self, i: usize, alpha: f32, share: f32) {
+        let slip = self.slip(i);
+        let c = slip.magnitude();
+        if c < f32::EPSILON {
+            return;
+        }
+        let t = slip / c;
 
-                if denom.abs() < f32::EPSILON {
-                    return 0.0;
-                }
+        let r = self.lever(self.anchors[i]);
+        let denom = self.invmass_sum(r, t) + alpha;
+        if denom < f32::EPSILON {
+            return;
+        }
+        let dlambda = (c - alpha * self.lambda_t[i]) / denom * share;
+        if self.lambda_t[i] + dlambda > self.material.static_friction * self.load(i) {
+            self.anchors[i] = self.points[i];
+            return;
+        }
 
-                (-C - alpha * self.lambda()) / (denom)
-            })
-            .collect()
+        self.lambda_t[i] += dlambda;
+        self.apply(r, t * dlambda);
+    }
+
+    // Normal lambda contact i has pushed with this substep, what its friction
+    // is bounded by
+    fn load(&self, i: usize) -> f32 {
+        (self.lambda[i] - self.warm[i]).max(0.0)
     }
 }
 
@@ -242,139 +369,275 @@ impl Constraint for RColl {
         self.bodies.clone().to_vec()
     }
     fn compliance(&self) -> f32 {
-        self.compliance
+        self.material.compliance
     }
+    // Total normal lambda of the manifold
     fn lambda(&self) -> f32 {
-        0.0
-        // self.lambda
+        self.lambda.iter().sum()
     }
     fn reset_lambda(&mut self) {
-        for i in 0..self.contacts.len() {
+        for i in 0..self.points.len() {
             self.lambda[i] = 0.0;
+            self.warm[i] = 0.0;
+            self.lambda_t[i] = 0.0;
         }
     }
-    fn update_lambda(&mut self, dlambda: f32) {
-        // self.lambda += dlambda;
-    }
+    // Lambdas are kept per contact, see iterate
+    fn update_lambda(&mut self, _dlambda: f32) {}
     fn len(&self) -> usize {
         2
     }
 
+    // Deepest penetration of the manifold
     fn C(&self) -> f32 {
-        0.0
+        (0..self.points.len())
+            .map(|i| self.penetration(i))
+            .fold(0.0, f32::max)
     }
 
     fn dC(&self) -> Vec<Vec3> {
         vec![self.normal, -self.normal]
     }
 
-    fn dx(&self, dlambda: f32) -> Vec<Vec3> {
-        let dC = self.dC();
-
-        let mut result = vec![Vec3::zero(); self.len()];
-        for j in 0..self.contacts.len() {
-            for i in 0..self.len() {
-                result[i] += self.bodies()[i].borrow().invmass() * dC[i] * dlambda;
-            }
-        }
-        result
-    }
-
     fn iterate(&mut self, dt: f32) {
-        let mut dx_vec = vec![vec![Vec3::zero(), Vec3::zero()]; self.contacts.len()];
-        let mut dq_vec = vec![(Quat::zero(), Quat::zero()); self.contacts.len()];
-        let mut dlambda_vec = vec![0.0; self.contacts.len()];
-
-        for i in 0..self.contacts.len() {
-            let dlambda = self.dlambda_vec(dt)[i];
-            dx_vec[i] = self.dx(dlambda);
-            dq_vec[i] = self.dq_vec(dlambda, i);
-            dlambda_vec[i] = dlambda;
+        let alpha = self.compliance() / (dt * dt);
+        let gamma = alpha * self.material.damping * dt;
+
+        for i in 0..self.points.len() {
+            let r = self.r()[i];
+            let c = self.penetration(i);
+            let denom = (1.0 + gamma) * self.invmass_sum(r, self.normal) + alpha;
+            if denom < f32::EPSILON {
+                continue;
+            }
+            // Damping works against the motion along the normal this substep
+            let damping = if gamma > 0.0 {
+                gamma * (c - self.previous_penetration(i))
+            } else {
+                0.0
+            };
+
+            // Contacts only push, the accumulated lambda never turns negative
+            let dlambda =
+                ((c - alpha * self.lambda[i] + damping) / denom).max(-self.lambda[i]);
+            self.lambda[i] += dlambda;
+            self.apply(r, self.normal * dlambda);
         }
-        // self.update_lambda(dlambda);
 
-        for j in 0..self.contacts.len() {
-            self.lambda[j] += dlambda_vec[j];
-
-            self.bodies()[0]
-                .as_ref()
-                .borrow_mut()
-                .update_pos(dx_vec[j][0] * 1.0);
-            self.bodies()[1]
-                .as_ref()
-                .borrow_mut()
-                .update_pos(dx_vec[j][1] * 1.0);
-            if dq_vec[j].0.magnitude2() < f32::EPSILON*f32::EPSILON ||
-                dq_vec[j].1.magnitude2() < f32::EPSILON*f32::EPSILON {
-                    continue;;
-                }
-            self.bodies()[0].as_ref().borrow_mut().add_apos(dq_vec[j].0);
-            self.bodies()[1].as_ref().borrow_mut().add_apos(dq_vec[j].1);
+        // Friction once every contact has its share of the load. Each contact
+        // takes out of its slip as much as its share of the load, so the
+        // contacts hold for as long as the manifold as a whole could.
+        let touching: Vec<usize> = (0..self.points.len())
+            .filter(|i| self.load(*i) > 0.0)
+            .collect();
+        let load: f32 = touching.iter().map(|i| self.load(*i)).sum();
+        for i in touching {
+            self.stick(i, alpha, self.load(i) / load);
         }
     }
 
     fn velocity_update(&mut self, dt: f32) {
-        // println!("------");
-
         let normal = self.normal;
-        let mut dv_vec = vec![Vec3::zero(); self.contacts.len()];
-
         let r = self.r();
-
-        for i in 0..self.contacts.len() {
-            let v = (self.bodies[0].as_ref().borrow().vel()
-                + self.bodies[0].as_ref().borrow().avel().cross(r[i].0))
-                - (self.bodies[1].as_ref().borrow().vel()
-                    + self.bodies[1].as_ref().borrow().avel().cross(r[i].1));
-            // println!("{:?}", v);
-
-            let v_normal = normal.dot(v);
-            // println!("{}", v_normal);
-            let v_tangential = v - normal * v_normal;
-
-            if v_normal.abs() > 0.0 {
-                let v_normal_original = normal.dot(self.original_velocity[i]);
-                // println!("{}", v_normal_original);
-                let e = 0.9;
-                dv_vec[i] += normal * (-v_normal + f32::min(-e * v_normal_original, 0.0));
+        // Contacts that did not push this substep are not touching
+        let active: Vec<usize> = (0..self.points.len())
+            .filter(|i| self.load(*i) > 0.0)
+            .collect();
+
+        // Every contact sees the same velocities, so their impulses are averaged
+        let mut dv_vec = vec![Vec3::zero(); self.points.len()];
+        for i in active.iter().copied() {
+            let v_normal = normal.dot(self.relative_velocity(r[i]));
+            // Whether the contact bounces and how hard both go by the speed it
+            // came in with, before the solve took it out
+            let approach = normal.dot(self.original_velocity[i]);
+            let e = if approach > self.restitution_threshold {
+                self.material.bounce()
             } else {
-                dv_vec[i] += -normal * v_normal;
-            }
+                0.0
+            };
+            dv_vec[i] = normal * (-v_normal - e * approach);
+        }
+        for i in active.iter().copied() {
+            self.apply_velocity(r[i], dv_vec[i], active.len() as f32);
+        }
 
+        // Dynamic friction, one contact at a time within its share of the load
+        for i in active.iter().copied() {
+            let v = self.relative_velocity(r[i]);
+            let v_tangential = v - normal * normal.dot(v);
             let v_tangential_abs = v_tangential.magnitude();
-            let u = 0.9;
             if v_tangential_abs > f32::EPSILON {
-                dv_vec[i] -= v_tangential / v_tangential_abs
-                    * f32::min(u * self.lambda[i].abs() / dt, v_tangential_abs);
+                let t = v_tangential / v_tangential_abs;
+                // The friction impulse is bounded, not the change in velocity
+                let max_dv =
+                    self.material.dynamic_friction * self.load(i) / dt * self.invmass_sum(r[i], t);
+                self.apply_velocity(r[i], -t * f32::min(max_dv, v_tangential_abs), 1.0);
             }
         }
 
-        // let dv: Vec3 = dv_vec.iter().sum::<Vector3<f32>>()/(self.contacts.len() as f32);
+        // Rolling and torsional friction, on the whole manifold at once
+        if !active.is_empty() {
+            let w = {
+                let [a, b] = [0, 1].map(|k| self.bodies[k].as_ref().borrow());
+                a.avel() - b.avel()
+            };
+            let w_normal = normal * normal.dot(w);
+            self.resist_spin(w - w_normal, self.material.rolling_friction, dt);
+            self.resist_spin(w_normal, self.material.torsional_friction, dt);
+        }
+    }
+}
 
-        for j in 0..self.contacts.len() {
-            // let p = dv_vec[j] / self.invmass_sum_vec()[j] / (self.contacts.len() as f32);
-            let p = dv_vec[j] / self.invmass_sum_vec()[j];
-            // let p = dv / self.invmass_sum_vec()[j];
+#[cfg(test)]
+mod rigidbody_constraint_test {
+    use super::*;
+    use crate::collision::chull::*;
+    use crate::collision::collider::*;
+    use crate::cube::*;
+    use crate::inertiatensor::*;
+    use crate::physics::*;
+
+    #[test]
+    fn penetration() {
+        let ground: Rc<RefCell<dyn Body>> = Rc::new(RefCell::new(RigidBody::new(
+            Vec3::zero(),
+            Quat::one(),
+            0.0,
+            zeroinertia_mass(),
+        )));
+        let cube: Rc<RefCell<dyn Body>> = Rc::new(RefCell::new(RigidBody::new(
+            vec3(0.0, 0.9, 0.0),
+            Quat::one(),
+            1.0,
+            cubeinertia_mass(1.0),
+        )));
+        let corners = [(-0.5, -0.5), (-0.5, 0.5), (0.5, 0.5), (0.5, -0.5)];
+        let mut contact = RColl::new(
+            [ground, cube.clone()],
+            corners
+                .iter()
+                .map(|(x, z)| (vec3(*x, 0.5, *z), vec3(*x, 0.4, *z)))
+                .collect(),
+            vec3(0.0, 1.0, 0.0),
+            // Frictionless, so only the normal solve moves the cube
+            Material::new(0.0, 0.0, 0.0).with_compliance(0.0, 0.0),
+        );
+        assert!((contact.C() - 0.1).abs() < 1e-6);
+
+        // Penetration is measured again as the cube moves, so repeated
+        // iterations settle instead of pushing it out again each time
+        for _ in 0..10 {
+            contact.iterate(1.0 / 60.0);
+        }
+        assert!(contact.C() < 1e-4);
+        assert!((cube.as_ref().borrow().pos().y - 1.0).abs() < 1e-3);
+        assert!(contact.lambda.iter().all(|lambda| *lambda >= 0.0));
+        assert!((contact.lambda() - 0.1).abs() < 1e-3);
+
+        // Separated contacts give back what they pushed, but never pull
+        cube.as_ref().borrow_mut().update_pos(vec3(0.0, 0.5, 0.0));
+        contact.iterate(1.0 / 60.0);
+        assert!(contact.lambda.iter().all(|lambda| *lambda == 0.0));
+        assert!((cube.as_ref().borrow().pos().y - 1.4).abs() < 1e-3);
+    }
 
-            for (i, body) in self.bodies.iter().enumerate() {
-                let new_vel = body.as_ref().borrow().vel()
-                    + p * body.as_ref().borrow().invmass() * (if i == 0 { 1.0 } else { -1.0 }) / (self.contacts.len() as f32);
-                body.as_ref().borrow_mut().set_vel(new_vel);
+    #[test]
+    fn resting_stack() {
+        let mut physics = Physics::new(vec3(0.0, -9.8, 0.0), SUBS, ITER);
+        physics.add_collider(PlaneCollider::new(vec3(0.0, 1.0, 0.0), -0.5));
+        let cubes: Vec<_> = (0..3)
+            .map(|i| {
+                let rot = Quat::from_angle_y(Deg(10.0 * i as f32));
+                let cube = physics.add_body(RigidBody::new(
+                    vec3(0.05 * i as f32, 1.02 * i as f32, 0.0),
+                    rot,
+                    1.0,
+                    cubeinertia_mass(1.0),
+                ));
+                physics.add_collider(PolyhedraCollider::new(
+                    &(cube.clone() as Rc<RefCell<dyn Body>>),
+                    cube_polyhedra(1.0),
+                ));
+                cube
+            })
+            .collect();
 
-                let new_avel = body.as_ref().borrow().avel()
-                    + body.as_ref().borrow().invinertia()
-                        * ((if i == 0 { r[j].0 } else { r[j].1 }).cross(p))
-                        * (if i == 0 { 1.0 } else { -1.0 });
-                body.as_ref().borrow_mut().set_avel(new_avel);
-            }
+        for _ in 0..120 {
+            physics.update(1.0 / 60.0);
+        }
+        for (i, cube) in cubes.iter().enumerate() {
+            let cube = cube.as_ref().borrow();
+            assert!((cube.pos().y - i as f32).abs() < 1e-2);
+            assert!(cube.vel().magnitude() < 1e-1);
         }
+    }
+
+    #[test]
+    fn static_friction() {
+        // How far a cube left on a slope moves in two seconds
+        let slide = |degrees: f32| {
+            let angle = degrees.to_radians();
+            let normal = vec3(-angle.sin(), angle.cos(), 0.0);
+            let mut physics = Physics::new(vec3(0.0, -9.8, 0.0), SUBS, ITER);
+            physics.add_collider(PlaneCollider::new(normal, 0.0));
+            let cube = physics.add_body(RigidBody::new(
+                normal * 0.5,
+                Quat::from_angle_z(Rad(angle)),
+                1.0,
+                cubeinertia_mass(1.0),
+            ));
+            physics.add_collider(PolyhedraCollider::new(
+                &(cube.clone() as Rc<RefCell<dyn Body>>),
+                cube_polyhedra(1.0),
+            ));
+
+            for _ in 0..120 {
+                physics.update(1.0 / 60.0);
+            }
+            let moved = cube.as_ref().borrow().pos() - normal * 0.5;
+            moved.magnitude()
+        };
 
-        // let vel = self.bodies[0].as_ref().borrow().vel();
-        // self.bodies[0].as_ref().borrow_mut().set_vel(vel - normal*vel.dot(normal));
+        assert!(slide(30.0) < 1e-3);
+        assert!(slide(60.0) > 1.0);
+    }
 
-        // let v_old = v;
-        // println!("{}", normal.dot(v));
-        // println!("{}", normal.dot(self.bodies[0].as_ref().borrow().vel()));
-        // println!("{}", normal.dot(self.bodies[1].as_ref().borrow().vel()));
+    #[test]
+    fn dynamic_friction() {
+        // How far a cube with friction coefficient u slides down a 45 degree
+        // slope in its first second, and how far it should
+        let slide = |u: f32| {
+            let angle = 45.0f32.to_radians();
+            let normal = vec3(-angle.sin(), angle.cos(), 0.0);
+            let material = Material::new(u, u, 0.0);
+            let mut physics = Physics::new(vec3(0.0, -9.8, 0.0), SUBS, ITER);
+            physics.add_collider(PlaneCollider::new(normal, 0.0).with_material(material));
+            let cube = physics.add_body(RigidBody::new(
+                normal * 0.5,
+                Quat::from_angle_z(Rad(angle)),
+                1.0,
+                cubeinertia_mass(1.0),
+            ));
+            physics.add_collider(
+                PolyhedraCollider::new(
+                    &(cube.clone() as Rc<RefCell<dyn Body>>),
+                    cube_polyhedra(1.0),
+                )
+                .with_material(material),
+            );
+
+            for _ in 0..60 {
+                physics.update(1.0 / 60.0);
+            }
+            let moved = (cube.as_ref().borrow().pos() - normal * 0.5).magnitude();
+            let expected = 0.5 * 9.8 * (angle.sin() - u * angle.cos());
+            (moved, expected)
+        };
+
+        for u in [0.3, 0.5] {
+            let (moved, expected) = slide(u);
+            assert!((moved - expected).abs() < 0.03 * expected);
+        }
     }
 }